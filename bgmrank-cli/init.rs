@@ -1,9 +1,15 @@
 use enumset::{EnumSet, EnumSetType};
 use getopts::{Matches, Options};
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::Duration;
 use strum::IntoEnumIterator;
 
-use libbgmrank::{Category, State};
+use libbgmrank::{Category, Date, Source, State, COOKIE_ENV_VAR};
+
+const DEFAULT_JOBS: usize = 4;
+/// Bangumi throttles clients sending requests too quickly.
+const DEFAULT_INTERVAL_MS: u64 = 500;
 
 fn get_args() -> (String, Vec<String>) {
     let mut args = std::env::args();
@@ -23,12 +29,106 @@ fn get_opts() -> Options {
         "CAT",
     );
     opts.optmulti("s", "state", &list_enum_str::<State>().join(", "), "STATE");
+    opts.optopt(
+        "",
+        "from-html",
+        "read saved list pages from DIR/<category>/<state>/ instead of fetching",
+        "DIR",
+    );
+    opts.optopt(
+        "",
+        "from-json",
+        "read collections from FILE exported in the format of the Bangumi API \
+         instead of fetching",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "source",
+        &format!(
+            "where to fetch collections from: {} (default html)",
+            list_enum_str::<Source>().join(", ")
+        ),
+        "SOURCE",
+    );
+    opts.optopt(
+        "",
+        "since",
+        "only count items collected on or after DATE, e.g. 2020-1-1",
+        "DATE",
+    );
+    opts.optopt(
+        "",
+        "until",
+        "only count items collected on or before DATE",
+        "DATE",
+    );
+    opts.optopt(
+        "j",
+        "jobs",
+        &format!(
+            "number of pages to fetch in parallel (default {})",
+            DEFAULT_JOBS
+        ),
+        "N",
+    );
+    opts.optopt(
+        "",
+        "interval",
+        &format!(
+            "minimum milliseconds between the starts of requests (default {})",
+            DEFAULT_INTERVAL_MS
+        ),
+        "MS",
+    );
+    opts.optopt(
+        "",
+        "cookie-file",
+        &format!(
+            "send the session cookies in FILE to see private collections \
+             (default: ${})",
+            COOKIE_ENV_VAR
+        ),
+        "FILE",
+    );
+    opts.optflag(
+        "l",
+        "list",
+        "list the items with their ratings and comments",
+    );
+    opts.optflag("", "by-year", "show rating stats by release year");
+    opts.optflag(
+        "",
+        "by-length",
+        "show rating stats by number of episodes or volumes",
+    );
+    opts.optflag(
+        "",
+        "vs-site",
+        "compare the ratings with the site scores by tag",
+    );
+    opts.optflag(
+        "",
+        "lenient",
+        "skip items which fail to parse instead of stopping",
+    );
+    opts.optflag(
+        "",
+        "check-counts",
+        "warn if the number of items differs from the profile (html source only)",
+    );
+    opts.optflag("", "no-cache", "don't read or write the page cache");
+    opts.optflag("", "refresh", "refetch all pages and update the cache");
     opts.optflag("h", "help", "print this help menu");
     opts
 }
 
 fn show_usage_and_exit(program: String, opts: Options, code: i32) -> ! {
-    let brief = format!("Usage: {} [options] username", program);
+    let brief = format!(
+        "Usage: {0} [options] username\n       {0} [options] --from-html DIR\n       \
+         {0} [options] --from-json FILE",
+        program
+    );
     print!("{}", opts.usage(&brief));
     std::process::exit(code)
 }
@@ -37,6 +137,22 @@ pub struct Args {
     pub username: String,
     pub categories: EnumSet<Category>,
     pub states: EnumSet<State>,
+    pub from_html: Option<PathBuf>,
+    pub from_json: Option<PathBuf>,
+    pub source: Source,
+    pub since: Option<Date>,
+    pub until: Option<Date>,
+    pub jobs: usize,
+    pub interval: Duration,
+    pub cookie_file: Option<PathBuf>,
+    pub lenient: bool,
+    pub check_counts: bool,
+    pub list: bool,
+    pub by_year: bool,
+    pub by_length: bool,
+    pub vs_site: bool,
+    pub no_cache: bool,
+    pub refresh: bool,
 }
 
 fn process_opt_list<E: EnumSetType + FromStr + Into<&'static str>>(
@@ -61,18 +177,74 @@ fn process_opt_list<E: EnumSetType + FromStr + Into<&'static str>>(
     Ok(result)
 }
 
+fn parse_date_opt(matches: &Matches, name: &str) -> Result<Option<Date>, String> {
+    match matches.opt_str(name) {
+        Some(date) => match date.parse() {
+            Ok(date) => Ok(Some(date)),
+            Err(_) => Err(format!("invalid date '{}'", date)),
+        },
+        None => Ok(None),
+    }
+}
+
 fn parse_opts(mut matches: Matches) -> Result<Args, String> {
-    if matches.free.len() != 1 {
-        return Err(String::from("username not specified"));
+    let from_html = matches.opt_str("from-html").map(PathBuf::from);
+    let from_json = matches.opt_str("from-json").map(PathBuf::from);
+    if from_html.is_some() && from_json.is_some() {
+        return Err(String::from(
+            "--from-html and --from-json can't be used together",
+        ));
     }
+    let username = match matches.free.len() {
+        1 => matches.free.remove(0),
+        0 if from_html.is_some() || from_json.is_some() => String::new(),
+        _ => return Err(String::from("username not specified")),
+    };
+    let source = match matches.opt_str("source") {
+        Some(source) => match source.parse() {
+            Ok(source) => source,
+            Err(_) => return Err(format!("unknown source '{}'", source)),
+        },
+        None => Source::default(),
+    };
+    let jobs = match matches.opt_str("j") {
+        Some(jobs) => match jobs.parse() {
+            Ok(jobs) if jobs > 0 => jobs,
+            _ => return Err(format!("invalid number of jobs '{}'", jobs)),
+        },
+        None => DEFAULT_JOBS,
+    };
+    let interval = match matches.opt_str("interval") {
+        Some(interval) => match interval.parse() {
+            Ok(interval) => interval,
+            Err(_) => return Err(format!("invalid interval '{}'", interval)),
+        },
+        None => DEFAULT_INTERVAL_MS,
+    };
     Ok(Args {
-        username: matches.free.remove(0),
+        username,
         categories: process_opt_list::<Category>(
             "category",
             matches.opt_strs("c"),
             Category::Anime,
         )?,
         states: process_opt_list::<State>("state", matches.opt_strs("s"), State::Collect)?,
+        from_html,
+        from_json,
+        source,
+        since: parse_date_opt(&matches, "since")?,
+        until: parse_date_opt(&matches, "until")?,
+        jobs,
+        interval: Duration::from_millis(interval),
+        cookie_file: matches.opt_str("cookie-file").map(PathBuf::from),
+        lenient: matches.opt_present("lenient"),
+        check_counts: matches.opt_present("check-counts"),
+        list: matches.opt_present("l"),
+        by_year: matches.opt_present("by-year"),
+        by_length: matches.opt_present("by-length"),
+        vs_site: matches.opt_present("vs-site"),
+        no_cache: matches.opt_present("no-cache"),
+        refresh: matches.opt_present("refresh"),
     })
 }
 
@@ -82,7 +254,7 @@ pub fn handle_opts() -> Args {
     let matches = match opts.parse(&args) {
         Ok(m) => m,
         Err(f) => {
-            println!("{}", f.to_string());
+            println!("{}", f);
             show_usage_and_exit(program, opts, 2);
         }
     };
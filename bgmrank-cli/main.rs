@@ -1,33 +1,211 @@
-use libbgmrank::{Histogram, Item, MAX_RATING};
+use crate::progress::ReportingFetcher;
+use libbgmrank::{
+    CollectionSource, Date, Error, FetchOptions, Fetcher, GroupStats, Histogram, HtmlDirSource,
+    Item, JsonExport, Source, MAX_RATING,
+};
+use std::collections::hash_map::{Entry, HashMap};
+use std::fmt::{self, Display};
 
 mod init;
+mod progress;
 
-fn get_all_items(args: &init::Args) -> Vec<Item> {
-    let mut result = vec![];
-    for category in args.categories.iter() {
-        for state in args.states.iter() {
-            println!("fetching {}: {}/{}", args.username, category, state);
-            result.extend(libbgmrank::get_items(
-                &args.username,
-                category,
-                state,
-                |page| {
-                    println!("  fetching page {}...", page);
-                },
-            ));
+fn create_fetcher(args: &init::Args) -> Result<Fetcher, Error> {
+    let options = FetchOptions {
+        source: args.source,
+        jobs: args.jobs,
+        interval: args.interval,
+        lenient: args.lenient,
+        cache: !args.no_cache,
+        refresh: args.refresh,
+        cookie_file: args.cookie_file.clone(),
+    };
+    Fetcher::builder().options(&options)?.build()
+}
+
+fn create_source(args: &init::Args) -> Result<Box<dyn CollectionSource>, Error> {
+    if let Some(dir) = &args.from_html {
+        let mut source = HtmlDirSource::new(dir);
+        if args.lenient {
+            source = source.lenient(|error| eprintln!("warning: skipped an item: {}", error));
+        }
+        return Ok(Box::new(source));
+    }
+    if let Some(path) = &args.from_json {
+        return Ok(Box::new(JsonExport::from_file(path)?));
+    }
+    // The API source is used to avoid scraping pages, so don't fetch the
+    // profile page with it.
+    let check_counts = args.check_counts && args.source == Source::Html;
+    Ok(Box::new(ReportingFetcher::new(
+        create_fetcher(args)?,
+        check_counts,
+    )))
+}
+
+fn get_all_items(source: &dyn CollectionSource, args: &init::Args) -> Result<Vec<Item>, Error> {
+    let lists: Vec<_> = args
+        .categories
+        .iter()
+        .flat_map(|category| args.states.iter().map(move |state| (category, state)))
+        .collect();
+    let mut items = dedup_items(source.get_all_items(&args.username, &lists)?);
+    if args.since.is_some() || args.until.is_some() {
+        items.retain(|item| {
+            item.collected_at.is_some_and(|date| {
+                args.since.is_none_or(|since| date >= since)
+                    && args.until.is_none_or(|until| date <= until)
+            })
+        });
+    }
+    Ok(items)
+}
+
+/// Keeps a single copy of each item which is in several of the lists,
+/// e.g. when it is moved between them while fetching.
+///
+/// The copy with a rating is kept, so that an item moved from the wish
+/// list to the collection doesn't lose its rating, and otherwise the one
+/// collected last. Items without an id (0) can't be told apart, so they
+/// are all kept.
+fn dedup_items(items: Vec<Item>) -> Vec<Item> {
+    let mut result: Vec<Item> = vec![];
+    let mut positions = HashMap::new();
+    for item in items {
+        if item.id == 0 {
+            result.push(item);
+            continue;
+        }
+        match positions.entry(item.id) {
+            Entry::Vacant(entry) => {
+                entry.insert(result.len());
+                result.push(item);
+            }
+            Entry::Occupied(entry) => {
+                let kept = &mut result[*entry.get()];
+                if dedup_key(&item) > dedup_key(kept) {
+                    *kept = item;
+                }
+            }
         }
     }
-    println!();
     result
 }
 
+fn dedup_key(item: &Item) -> (bool, Option<Date>) {
+    (item.rating.is_some(), item.collected_at)
+}
+
+fn print_items(items: &[Item]) {
+    for item in items {
+        let rating = match item.rating {
+            Some(rating) => format!("{:2}", rating),
+            None => String::from(" ?"),
+        };
+        let title = item.title_cn.as_deref().unwrap_or(&item.title);
+        match item.site_score {
+            Some(score) => println!("{} {} ({:.1})", rating, title, score),
+            None => println!("{} {}", rating, title),
+        }
+        if let Some(comment) = &item.comment {
+            println!("   {}", comment);
+        }
+    }
+    println!();
+}
+
+/// Buckets of lengths, roughly matching one-shots, single-cour,
+/// two-cour and longer series.
+const LENGTH_RANGES: [(u32, u32); 5] = [(1, 1), (2, 13), (14, 26), (27, 52), (53, u32::MAX)];
+
+fn length_range(episodes: u32) -> LengthRange {
+    let &(min, max) = LENGTH_RANGES
+        .iter()
+        .find(|(_, max)| episodes <= *max)
+        .unwrap();
+    LengthRange(min, max)
+}
+
+/// Prints how much higher than the site scores the items are rated,
+/// overall and by tag.
+fn print_deviations(items: &[Item]) {
+    let deviation = match libbgmrank::get_deviation(items) {
+        Some(deviation) => deviation,
+        None => {
+            println!("vs site: no items with site scores");
+            println!();
+            return;
+        }
+    };
+    println!(
+        "vs site: {:+.2} over {} items",
+        deviation.avg, deviation.count
+    );
+    for tag in libbgmrank::generate_tag_deviations(items) {
+        println!(
+            "{:+.2} {}: {}",
+            tag.deviation.avg, tag.tag, tag.deviation.count
+        );
+    }
+    println!();
+}
+
+fn print_group_stats<K: Display>(groups: Vec<GroupStats<K>>) {
+    for group in groups {
+        println!(
+            "{} {}: {}/{}",
+            group.stats.rating, group.key, group.stats.rated, group.stats.total
+        );
+    }
+    println!();
+}
+
+/// A range of lengths, displayed like `2-13` or `53+`.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct LengthRange(u32, u32);
+
+impl Display for LengthRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.0, self.1) {
+            (min, max) if min == max => write!(f, "{}", min),
+            (min, u32::MAX) => write!(f, "{}+", min),
+            (min, max) => write!(f, "{}-{}", min, max),
+        }
+    }
+}
+
 const MAX_COL_WIDTH: usize = 70;
 
 fn main() {
     let args = init::handle_opts();
-    let all_items = get_all_items(&args);
+    let result = create_source(&args).and_then(|source| get_all_items(&*source, &args));
+    let all_items = match result {
+        Ok(items) => items,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if args.list {
+        print_items(&all_items);
+    }
     let hist: Histogram = all_items.iter().collect();
 
+    if args.by_year {
+        let groups = libbgmrank::generate_stats_by(&all_items, |item| {
+            item.release_date.map(|date| date.year)
+        });
+        print_group_stats(groups);
+    }
+    if args.by_length {
+        let groups =
+            libbgmrank::generate_stats_by(&all_items, |item| item.episodes.map(length_range));
+        print_group_stats(groups);
+    }
+
+    if args.vs_site {
+        print_deviations(&all_items);
+    }
+
     for tag_stats in libbgmrank::generate_tag_stats(&all_items) {
         println!(
             "{} {}: {}/{}",
@@ -47,3 +225,39 @@ fn main() {
     println!("rated: {}/{}", stats.rated, stats.total);
     println!("rating: {}", stats.rating);
 }
+
+#[cfg(test)]
+mod test {
+    use super::dedup_items;
+    use libbgmrank::{Date, Item, State};
+
+    fn item(id: u32, state: State, rating: Option<u8>) -> Item {
+        Item {
+            id,
+            rating,
+            state: Some(state),
+            collected_at: Some(Date {
+                year: 2020,
+                month: 1,
+                day: 1,
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_dedup_items() {
+        let items = vec![
+            // Caught while moving from the wish list to the collection.
+            item(1, State::Wish, None),
+            item(2, State::Wish, None),
+            item(0, State::Wish, None),
+            item(1, State::Collect, Some(8)),
+            item(0, State::Collect, Some(7)),
+        ];
+        let items = dedup_items(items);
+        let summary: Vec<_> = items.iter().map(|i| (i.id, i.rating)).collect();
+        assert_eq!(summary, [(1, Some(8)), (2, None), (0, None), (0, Some(7))]);
+        assert_eq!(items[0].state, Some(State::Collect));
+    }
+}
@@ -0,0 +1,205 @@
+use libbgmrank::{Category, CollectionSource, Error, Fetcher, Item, Progress, State};
+use std::collections::HashMap;
+use std::io::{self, IsTerminal, Write};
+
+const BAR_WIDTH: usize = 30;
+
+/// Renders fetch progress as a progress bar on terminals, and as plain
+/// lines otherwise.
+pub struct ProgressReporter {
+    is_tty: bool,
+    lists: usize,
+    page_counts: HashMap<(Category, State), usize>,
+    item_counts: HashMap<(Category, State), usize>,
+    pages_done: usize,
+    items: usize,
+}
+
+impl ProgressReporter {
+    pub fn new(lists: usize) -> Self {
+        ProgressReporter {
+            is_tty: io::stdout().is_terminal(),
+            lists,
+            page_counts: HashMap::new(),
+            item_counts: HashMap::new(),
+            pages_done: 0,
+            items: 0,
+        }
+    }
+
+    pub fn report(&mut self, progress: Progress) {
+        match progress {
+            Progress::PageParsed { items, .. } => {
+                self.pages_done += 1;
+                self.items += items;
+            }
+            Progress::PageCountKnown {
+                category,
+                state,
+                pages,
+                total_items,
+            } => {
+                self.page_counts.insert((category, state), pages);
+                if let Some(total_items) = total_items {
+                    self.item_counts.insert((category, state), total_items);
+                }
+            }
+            Progress::ItemSkipped {
+                category,
+                state,
+                page,
+                ref error,
+            } => {
+                if self.is_tty {
+                    // Clear the bar, which is redrawn below the warning.
+                    let mut stdout = io::stdout();
+                    let _ = stdout.write_all(b"\r\x1b[K");
+                    let _ = stdout.flush();
+                }
+                eprintln!(
+                    "warning: skipped an item on {}/{} page {}: {}",
+                    category, state, page, error
+                );
+            }
+            _ => {}
+        }
+        if self.is_tty {
+            self.draw_bar(&progress);
+        } else {
+            self.print_line(&progress);
+        }
+    }
+
+    fn total_pages(&self) -> usize {
+        // Lists whose page count is unknown have at least one page.
+        let unknown = self.lists.saturating_sub(self.page_counts.len());
+        self.page_counts.values().sum::<usize>() + unknown
+    }
+
+    /// Returns the total number of items, if it's known for all lists.
+    fn total_items(&self) -> Option<usize> {
+        if self.item_counts.len() < self.lists {
+            return None;
+        }
+        Some(self.item_counts.values().sum())
+    }
+
+    fn draw_bar(&self, progress: &Progress) {
+        let total = self.total_pages().max(1);
+        let filled = BAR_WIDTH * self.pages_done.min(total) / total;
+        let items = match self.total_items() {
+            Some(total_items) => format!("{} of {} items", self.items, total_items),
+            None => format!("{} items", self.items),
+        };
+        let mut line = format!(
+            "\r\x1b[K[{}{}] page {}/{}, {}",
+            "#".repeat(filled),
+            "-".repeat(BAR_WIDTH - filled),
+            self.pages_done,
+            total,
+            items
+        );
+        match progress {
+            Progress::Retrying { delay, .. } => {
+                line += &format!(" (retrying in {:.1}s)", delay.as_secs_f32());
+            }
+            Progress::Finished { .. } => line += "\n",
+            _ => {}
+        }
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(line.as_bytes());
+        let _ = stdout.flush();
+    }
+
+    fn print_line(&self, progress: &Progress) {
+        match *progress {
+            Progress::PageStarted {
+                category,
+                state,
+                page,
+            } => match self.page_counts.get(&(category, state)) {
+                Some(pages) => println!(
+                    "  fetching {}/{} page {}/{}...",
+                    category, state, page, pages
+                ),
+                None => println!("  fetching {}/{} page {}...", category, state, page),
+            },
+            Progress::Retrying {
+                category,
+                state,
+                page,
+                attempt,
+                delay,
+            } => println!(
+                "  retrying {}/{} page {} in {:.1}s (attempt {})...",
+                category,
+                state,
+                page,
+                delay.as_secs_f32(),
+                attempt
+            ),
+            Progress::Finished { items } => println!("fetched {} items", items),
+            Progress::PageParsed { .. }
+            | Progress::PageCountKnown { .. }
+            | Progress::ItemSkipped { .. } => {}
+        }
+    }
+}
+
+/// A source fetching from the site and reporting the progress.
+pub struct ReportingFetcher {
+    fetcher: Fetcher,
+    /// Whether to compare the number of items with the profile.
+    check_counts: bool,
+}
+
+impl ReportingFetcher {
+    pub fn new(fetcher: Fetcher, check_counts: bool) -> Self {
+        ReportingFetcher {
+            fetcher,
+            check_counts,
+        }
+    }
+
+    /// Warns about lists whose number of items differs from the count on
+    /// the profile of the user, which usually means pages failed to parse
+    /// or the list changed while fetching.
+    fn check_counts(&self, username: &str, lists: &[(Category, State)], items: &[Item]) {
+        match self.fetcher.check_counts(username, lists, items) {
+            Ok(mismatches) => {
+                for mismatch in mismatches {
+                    eprintln!("warning: {}", mismatch);
+                }
+            }
+            Err(e) => eprintln!("warning: failed to check the number of items: {}", e),
+        }
+    }
+}
+
+impl CollectionSource for ReportingFetcher {
+    fn get_items(
+        &self,
+        username: &str,
+        category: Category,
+        state: State,
+    ) -> Result<Vec<Item>, Error> {
+        self.get_all_items(username, &[(category, state)])
+    }
+
+    fn get_all_items(
+        &self,
+        username: &str,
+        lists: &[(Category, State)],
+    ) -> Result<Vec<Item>, Error> {
+        println!("fetching {}:", username);
+        let mut reporter = ProgressReporter::new(lists.len());
+        let result = self
+            .fetcher
+            .get_all_items(username, lists, |progress| reporter.report(progress))?;
+        if self.check_counts {
+            self.check_counts(username, lists, &result);
+        }
+        println!();
+        Ok(result)
+    }
+}
@@ -1,39 +1,118 @@
-use clap::Parser;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser};
 use colored::Colorize;
 use either::Either;
-use libbgmrank::{Category, Rating, State};
+use libbgmrank::{
+    Category, CollectionSource, FetchOptions, Fetcher, HtmlDirSource, Id, Item, JsonExport,
+    Progress, Rating, Source, State, MAX_RATING,
+};
 use std::collections::HashMap;
 use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
 use unicode_width::UnicodeWidthStr;
 
 #[derive(Parser)]
 struct Opts {
     #[arg(name = "USER1")]
     user1: String,
-    #[arg(name = "USER2")]
-    user2: String,
+    #[arg(name = "USER2", required_unless_present = "friends")]
+    user2: Option<String>,
+    /// Compare USER1 with each of their friends instead, ranking the
+    /// friends by how similarly they rate, with the ones sharing few
+    /// rated items last
+    #[arg(long, conflicts_with = "USER2")]
+    friends: bool,
+    /// Read the collections of USER1, and of USER2 if given twice, from
+    /// list pages saved under DIR/<category>/<state>/ instead of fetching
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["from_json", "friends"])]
+    from_html: Vec<PathBuf>,
+    /// Read the collections of USER1, and of USER2 if given twice, from
+    /// FILE exported in the format of the Bangumi API instead of fetching
+    #[arg(long, value_name = "FILE", conflicts_with = "friends")]
+    from_json: Vec<PathBuf>,
+    /// Where to fetch collections from: html or api
+    #[arg(long, default_value = "html")]
+    source: Source,
+    /// Number of pages to fetch in parallel
+    #[arg(short, long, default_value_t = 4)]
+    jobs: usize,
+    /// Minimum milliseconds between the starts of requests
+    #[arg(long, value_name = "MS", default_value_t = 500)]
+    interval: u64,
+    /// Send the session cookies in FILE to see private collections
+    /// (default: $BGMRANK_COOKIE)
+    #[arg(long, value_name = "FILE")]
+    cookie_file: Option<PathBuf>,
+    /// Skip items which fail to parse instead of stopping
+    #[arg(long)]
+    lenient: bool,
+    /// Warn if the number of items differs from the profile (html
+    /// source only, and not with --friends)
+    #[arg(long)]
+    check_counts: bool,
+    /// Don't read or write the page cache
+    #[arg(long)]
+    no_cache: bool,
+    /// Refetch all pages and update the cache
+    #[arg(long)]
+    refresh: bool,
 }
 
 fn main() {
     let opts = Opts::parse();
-    println!("fetching {}:", &opts.user1);
-    let items1 = libbgmrank::get_items(&opts.user1, Category::Anime, State::Collect, |page| {
-        println!("  fetching page {}...", page);
-    });
-    println!("fetching {}:", &opts.user2);
-    let items2 = libbgmrank::get_items(&opts.user2, Category::Anime, State::Collect, |page| {
-        println!("  fetching page {}...", page);
-    });
+    if opts.from_html.len() > 2 || opts.from_json.len() > 2 {
+        Opts::command()
+            .error(
+                ErrorKind::TooManyValues,
+                "give a saved list for at most two users",
+            )
+            .exit();
+    }
+    match &opts.user2 {
+        Some(user2) => {
+            let sources = unwrap_or_exit(create_sources(&opts));
+            print_diff([&*sources[0], &*sources[1]], [&opts.user1, user2]);
+        }
+        None => {
+            // Checking the counts would fetch the profile of every friend.
+            let fetcher = ReportingFetcher::new(unwrap_or_exit(create_fetcher(&opts)), false);
+            print_friend_similarities(&fetcher, &opts.user1);
+        }
+    }
+}
+
+/// Creates the sources to read the collections of the two users from.
+/// Users without a saved list are fetched from the site.
+fn create_sources(opts: &Opts) -> Result<Vec<Box<dyn CollectionSource>>, libbgmrank::Error> {
+    let mut sources: Vec<Box<dyn CollectionSource>> = vec![];
+    for dir in &opts.from_html {
+        let mut source = HtmlDirSource::new(dir);
+        if opts.lenient {
+            source = source.lenient(|error| eprintln!("warning: skipped an item: {}", error));
+        }
+        sources.push(Box::new(source));
+    }
+    for path in &opts.from_json {
+        sources.push(Box::new(JsonExport::from_file(path)?));
+    }
+    // The API source is used to avoid scraping pages, so don't fetch the
+    // profile page with it.
+    let check_counts = opts.check_counts && opts.source == Source::Html;
+    while sources.len() < 2 {
+        let fetcher = create_fetcher(opts)?;
+        sources.push(Box::new(ReportingFetcher::new(fetcher, check_counts)));
+    }
+    Ok(sources)
+}
+
+fn print_diff(sources: [&dyn CollectionSource; 2], users: [&str; 2]) {
+    let items1 = get_items(sources[0], users[0]);
+    let items2 = get_items(sources[1], users[1]);
     println!();
 
-    let map1 = items1
-        .iter()
-        .map(|item| (item.id, item))
-        .collect::<HashMap<_, _>>();
-    let map2 = items2
-        .iter()
-        .map(|item| (item.id, item))
-        .collect::<HashMap<_, _>>();
+    let index1 = ItemIndex::new(&items1);
+    let index2 = ItemIndex::new(&items2);
     let mut list = Iterator::chain(
         items1.iter().map(Either::Left),
         items2.iter().map(Either::Right),
@@ -42,35 +121,236 @@ fn main() {
     list.sort_by(|&a, &b| Ord::cmp(&a.rating, &b.rating).reverse());
 
     for item in list {
+        let (item1, item2) = match item {
+            Either::Left(item) => (Some(item), index2.find(item)),
+            Either::Right(item) => (index1.find(item), Some(item)),
+        };
         let line = match item {
-            Either::Left(item) => match map2.get(&item.id) {
-                Some(&item2) => format!(
+            Either::Left(item) => match item2 {
+                Some(item2) => format!(
                     "~ {} {}..{}",
-                    PadTitle(&item.title),
+                    PadTitle(main_title(item)),
                     FormatRating(item.rating),
                     FormatRating(item2.rating),
                 )
                 .yellow(),
                 None => format!(
                     "- {} {}..",
-                    PadTitle(&item.title),
+                    PadTitle(main_title(item)),
                     FormatRating(item.rating)
                 )
                 .red(),
             },
             Either::Right(item) => {
-                if map1.contains_key(&item.id) {
+                if item1.is_some() {
                     continue;
                 }
                 format!(
                     "+ {}   ..{}",
-                    PadTitle(&item.title),
+                    PadTitle(main_title(item)),
                     FormatRating(item.rating),
                 )
                 .green()
             }
         };
         println!("{}", line);
+        if let Some(title) = original_title(item.into_inner()) {
+            println!("  {}", title.dimmed());
+        }
+        let comments = [("<", item1), (">", item2)];
+        for (mark, item) in comments {
+            if let Some(comment) = item.and_then(|item| item.comment.as_deref()) {
+                println!("  {} {}", mark, comment.italic());
+            }
+        }
+    }
+}
+
+fn print_friend_similarities(source: &ReportingFetcher, username: &str) {
+    let friends = unwrap_or_exit(source.fetcher.get_friends(username));
+    let items = get_items(source, username);
+    let mut results = vec![];
+    for friend in friends {
+        match source.get_items(&friend.username, Category::Anime, State::Collect) {
+            Ok(friend_items) => results.push((Similarity::new(&items, &friend_items), friend)),
+            Err(e) => eprintln!("warning: skipped {}: {}", friend.username, e),
+        }
+    }
+    println!();
+
+    // A few shared items say little about how similarly two users rate,
+    // so friends with fewer than MIN_SHARED go after the others, and ones
+    // without any item rated by both go last.
+    results.sort_by(|(a, _), (b, _)| {
+        let key = |similarity: &Similarity| (similarity.shared >= MIN_SHARED, similarity.score);
+        key(b).partial_cmp(&key(a)).unwrap()
+    });
+    for (similarity, friend) in results {
+        let score = match similarity.score {
+            Some(score) => format!("{:.2}", score),
+            None => String::from("   -"),
+        };
+        println!(
+            "{} {} ({}): {} shared",
+            score, friend.nickname, friend.username, similarity.shared
+        );
+    }
+}
+
+/// Minimum number of items rated by both users for the similarity to be
+/// ranked by its score.
+const MIN_SHARED: usize = 10;
+
+/// How similarly two users rate the items they both rated.
+struct Similarity {
+    /// From 0 when the ratings are as far apart as possible, to 1 when
+    /// they are all the same. `None` if there are no shared items.
+    score: Option<f32>,
+    /// Number of items rated by both users.
+    shared: usize,
+}
+
+impl Similarity {
+    fn new(items1: &[Item], items2: &[Item]) -> Self {
+        let index2 = ItemIndex::new(items2);
+        let diffs: Vec<_> = items1
+            .iter()
+            .filter_map(|item| {
+                let rating2 = index2.find(item)?.rating?;
+                Some(f32::from(item.rating?.abs_diff(rating2)))
+            })
+            .collect();
+        let avg_diff = diffs.iter().sum::<f32>() / diffs.len() as f32;
+        Similarity {
+            score: Some(1.0 - avg_diff / f32::from(MAX_RATING - 1)).filter(|_| !diffs.is_empty()),
+            shared: diffs.len(),
+        }
+    }
+}
+
+/// Looks up items by id. Items without an id, e.g. ones hand-written
+/// into an export, are matched by their titles instead, where both the
+/// localized and the original title have to agree, since different
+/// subjects like remakes often share one of them.
+struct ItemIndex<'a> {
+    by_id: HashMap<Id, &'a Item>,
+    by_titles: HashMap<Titles<'a>, &'a Item>,
+}
+
+type Titles<'a> = (Option<&'a str>, Option<&'a str>);
+
+impl<'a> ItemIndex<'a> {
+    fn new(items: &'a [Item]) -> Self {
+        let by_id = items.iter().map(|item| (item.id, item)).collect();
+        let by_titles = items.iter().map(|item| (titles(item), item)).collect();
+        ItemIndex { by_id, by_titles }
+    }
+
+    fn find(&self, item: &Item) -> Option<&'a Item> {
+        let found = match titles(item) {
+            _ if item.id != 0 => self.by_id.get(&item.id),
+            (None, None) => None,
+            titles => self.by_titles.get(&titles),
+        };
+        found.copied()
+    }
+}
+
+fn titles(item: &Item) -> Titles<'_> {
+    (item.title_cn.as_deref(), item.title_original.as_deref())
+}
+
+/// Returns the localized title if there is one.
+fn main_title(item: &Item) -> &str {
+    item.title_cn.as_deref().unwrap_or(&item.title)
+}
+
+/// Returns the original title to show under the localized one, if any.
+fn original_title(item: &Item) -> Option<&str> {
+    let title_original = item.title_original.as_deref()?;
+    Some(title_original).filter(|&title| title != main_title(item))
+}
+
+fn create_fetcher(opts: &Opts) -> Result<Fetcher, libbgmrank::Error> {
+    let options = FetchOptions {
+        source: opts.source,
+        jobs: opts.jobs,
+        interval: Duration::from_millis(opts.interval),
+        lenient: opts.lenient,
+        cache: !opts.no_cache,
+        refresh: opts.refresh,
+        cookie_file: opts.cookie_file.clone(),
+    };
+    Fetcher::builder().options(&options)?.build()
+}
+
+/// A source fetching from the site and printing the progress.
+struct ReportingFetcher {
+    fetcher: Fetcher,
+    /// Whether to compare the number of items with the profile.
+    check_counts: bool,
+}
+
+impl ReportingFetcher {
+    fn new(fetcher: Fetcher, check_counts: bool) -> Self {
+        ReportingFetcher {
+            fetcher,
+            check_counts,
+        }
+    }
+
+    /// Warns about lists whose number of items differs from the profile.
+    fn check_counts(&self, username: &str, lists: &[(Category, State)], items: &[Item]) {
+        match self.fetcher.check_counts(username, lists, items) {
+            Ok(mismatches) => {
+                for mismatch in mismatches {
+                    eprintln!("warning: {}", mismatch);
+                }
+            }
+            Err(e) => eprintln!("warning: failed to check the number of items: {}", e),
+        }
+    }
+}
+
+impl CollectionSource for ReportingFetcher {
+    fn get_items(
+        &self,
+        username: &str,
+        category: Category,
+        state: State,
+    ) -> Result<Vec<Item>, libbgmrank::Error> {
+        println!("fetching {}:", username);
+        let lists = [(category, state)];
+        let items = self
+            .fetcher
+            .get_all_items(username, &lists, |progress| match progress {
+                Progress::PageStarted { page, .. } => println!("  fetching page {}...", page),
+                Progress::Retrying { page, delay, .. } => {
+                    println!("  retrying page {} in {:.1}s...", page, delay.as_secs_f32())
+                }
+                Progress::ItemSkipped { page, error, .. } => {
+                    eprintln!("warning: skipped an item on page {}: {}", page, error)
+                }
+                _ => {}
+            })?;
+        if self.check_counts {
+            self.check_counts(username, &lists, &items);
+        }
+        Ok(items)
+    }
+}
+
+fn get_items(source: &dyn CollectionSource, username: &str) -> Vec<Item> {
+    unwrap_or_exit(source.get_items(username, Category::Anime, State::Collect))
+}
+
+fn unwrap_or_exit<T>(result: Result<T, libbgmrank::Error>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
     }
 }
 
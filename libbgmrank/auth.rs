@@ -0,0 +1,81 @@
+use crate::error::Error;
+use reqwest::header::HeaderValue;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// The environment variable the CLIs read the session cookie from.
+pub const COOKIE_ENV_VAR: &str = "BGMRANK_COOKIE";
+
+/// Cookies of a logged-in session, sent with every request so that
+/// private collections can be fetched.
+///
+/// The value is never included in `Debug` output or error messages.
+#[derive(Clone)]
+pub struct SessionCookie(HeaderValue);
+
+impl SessionCookie {
+    /// Creates a session from a `Cookie` header value, e.g.
+    /// `chii_auth=...; chii_sid=...`.
+    pub fn new(cookie: &str) -> Result<Self, Error> {
+        let mut value = HeaderValue::from_str(cookie.trim()).map_err(|_| Error::InvalidCookie)?;
+        if value.is_empty() {
+            return Err(Error::InvalidCookie);
+        }
+        value.set_sensitive(true);
+        Ok(SessionCookie(value))
+    }
+
+    /// Reads the cookies from a file. Each non-empty line not starting
+    /// with `#` is taken as one or more `name=value` pairs.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let content = fs::read_to_string(path)?;
+        let cookie = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .collect::<Vec<_>>()
+            .join("; ");
+        Self::new(&cookie)
+    }
+
+    /// Reads the cookies from the `BGMRANK_COOKIE` environment variable,
+    /// returning `None` if it's not set.
+    pub fn from_env() -> Option<Result<Self, Error>> {
+        let cookie = std::env::var(COOKIE_ENV_VAR).ok()?;
+        Some(Self::new(&cookie))
+    }
+
+    pub(crate) fn header_value(&self) -> &HeaderValue {
+        &self.0
+    }
+}
+
+impl fmt::Debug for SessionCookie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SessionCookie(..)")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SessionCookie;
+    use std::fs;
+
+    #[test]
+    fn test_session_cookie() {
+        let path = std::env::temp_dir().join(format!("bgmrank-cookie-{}", std::process::id()));
+        fs::write(
+            &path,
+            "# exported cookies\nchii_auth=secret\n\nchii_sid=abc\n",
+        )
+        .unwrap();
+        let cookie = SessionCookie::from_file(&path).unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!(cookie.header_value(), "chii_auth=secret; chii_sid=abc");
+        assert!(!format!("{:?}", cookie).contains("secret"));
+
+        assert!(SessionCookie::new("  ").is_err());
+        assert!(SessionCookie::new("a=b\nc=d").is_err());
+    }
+}
@@ -0,0 +1,142 @@
+use crate::data::{Category, State};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// Identifies a single list page.
+#[derive(Clone, Copy)]
+pub struct PageKey<'a> {
+    pub username: &'a str,
+    pub category: Category,
+    pub state: State,
+    pub page: usize,
+}
+
+/// An on-disk cache of raw list pages.
+///
+/// Pages are stored as `<dir>/<username>/<category>/<state>/<page>.html`,
+/// and entries older than the TTL are treated as missing. Pages fetched
+/// with a session cookie may include private items, so they are kept
+/// separately under `<dir>/.auth/` instead.
+pub struct PageCache {
+    dir: PathBuf,
+    ttl: Duration,
+    refresh: bool,
+    authenticated: bool,
+}
+
+impl PageCache {
+    pub fn new(dir: impl Into<PathBuf>, ttl: Duration) -> Self {
+        PageCache {
+            dir: dir.into(),
+            ttl,
+            refresh: false,
+            authenticated: false,
+        }
+    }
+
+    /// Returns the platform cache directory for bgmrank, if any can be
+    /// determined from the environment.
+    pub fn default_dir() -> Option<PathBuf> {
+        let base = std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("LOCALAPPDATA").map(PathBuf::from))
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))?;
+        Some(base.join("bgmrank"))
+    }
+
+    /// When set, existing entries are ignored but freshly fetched pages
+    /// are still written to the cache.
+    pub fn refresh(mut self, refresh: bool) -> Self {
+        self.refresh = refresh;
+        self
+    }
+
+    /// Sets whether pages are fetched with a session cookie. Set by the
+    /// fetcher.
+    pub(crate) fn authenticated(mut self, authenticated: bool) -> Self {
+        self.authenticated = authenticated;
+        self
+    }
+
+    fn path(&self, key: &PageKey) -> PathBuf {
+        let category: &str = key.category.into();
+        let state: &str = key.state.into();
+        // Usernames are escaped, so they can never clash with `.auth`.
+        let dir = if self.authenticated {
+            self.dir.join(".auth")
+        } else {
+            self.dir.clone()
+        };
+        dir.join(escape_component(key.username))
+            .join(category)
+            .join(state)
+            .join(format!("{}.html", key.page))
+    }
+
+    pub fn get(&self, key: &PageKey) -> Option<String> {
+        if self.refresh {
+            return None;
+        }
+        let path = self.path(key);
+        let age = fs::metadata(&path).ok()?.modified().ok()?.elapsed().ok()?;
+        if age > self.ttl {
+            return None;
+        }
+        fs::read_to_string(path).ok()
+    }
+
+    pub fn put(&self, key: &PageKey, html: &str) -> io::Result<()> {
+        let path = self.path(key);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(path, html)
+    }
+}
+
+/// Makes the username safe to use as a single path component.
+fn escape_component(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '-' => c.to_string(),
+            _ => format!("%{:02X}", c as u32),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PageCache, PageKey};
+    use crate::data::{Category, State};
+    use std::time::Duration;
+
+    #[test]
+    fn test_cache_entries() {
+        let dir = std::env::temp_dir().join(format!("bgmrank-cache-{}", std::process::id()));
+        let cache = PageCache::new(&dir, Duration::from_secs(60));
+        let key = PageKey {
+            username: "../someone",
+            category: Category::Anime,
+            state: State::Collect,
+            page: 2,
+        };
+        assert_eq!(cache.get(&key), None);
+        cache.put(&key, "<html></html>").unwrap();
+        assert_eq!(cache.get(&key).as_deref(), Some("<html></html>"));
+        assert!(dir.join("%2E%2E%2Fsomeone/anime/collect/2.html").is_file());
+
+        let other = PageKey { page: 3, ..key };
+        assert_eq!(cache.get(&other), None);
+
+        let cache = PageCache::new(&dir, Duration::from_secs(60)).refresh(true);
+        assert_eq!(cache.get(&key), None);
+
+        std::thread::sleep(Duration::from_millis(20));
+        let cache = PageCache::new(&dir, Duration::from_millis(10));
+        assert_eq!(cache.get(&key), None);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,7 +1,11 @@
+use crate::error::ParseDateError;
 use enumset::EnumSetType;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
 use strum::{Display, EnumIter, EnumString, IntoStaticStr};
 
-#[derive(Display, EnumIter, EnumSetType, EnumString, IntoStaticStr)]
+#[derive(Debug, Display, EnumIter, EnumSetType, EnumString, Hash, IntoStaticStr)]
 #[strum(serialize_all = "snake_case")]
 pub enum Category {
     Anime,
@@ -11,7 +15,7 @@ pub enum Category {
     Real,
 }
 
-#[derive(Display, EnumIter, EnumSetType, EnumString, IntoStaticStr)]
+#[derive(Debug, Display, EnumIter, EnumSetType, EnumString, Hash, IntoStaticStr)]
 #[strum(serialize_all = "snake_case")]
 pub enum State {
     Wish,
@@ -21,14 +25,226 @@ pub enum State {
     Dropped,
 }
 
+pub const DEFAULT_BASE_URL: &str = "https://bgm.tv";
+
 pub type Id = u32;
 pub type Rating = u8;
 pub const MAX_RATING: Rating = 10;
 
-#[derive(Default)]
+/// A calendar date. Dates order chronologically.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// Parses dates in the form of `2019-4-6` or `2019-04-06`.
+impl FromStr for Date {
+    type Err = ParseDateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().splitn(3, '-').map(|part| part.parse().ok());
+        let mut next = || parts.next().flatten().ok_or(ParseDateError);
+        let (year, month, day) = (next()?, next()?, next()?);
+        if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+            return Err(ParseDateError);
+        }
+        Ok(Date {
+            year,
+            month: month as u8,
+            day: day as u8,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default)]
 pub struct Item {
     pub id: Id,
+    /// The title as shown on list pages, i.e. the original title if the
+    /// subject has a localized one as well.
     pub title: String,
+    /// The localized (Chinese) title, if the subject has one.
+    pub title_cn: Option<String>,
+    pub title_original: Option<String>,
     pub rating: Option<Rating>,
     pub tags: Vec<String>,
+    /// When the item was put into the list.
+    pub collected_at: Option<Date>,
+    /// The short comment the user left on the item.
+    pub comment: Option<String>,
+    /// The metadata line of the subject, listing e.g. the number of
+    /// episodes, the release date and the staff, separated by `/`.
+    pub info: Option<String>,
+    pub release_date: Option<Date>,
+    /// The number of episodes, or volumes for books.
+    pub episodes: Option<u32>,
+    /// The URL of the cover image of the subject, if it has one.
+    pub cover_url: Option<String>,
+    /// The rank of the subject on the site, if it's ranked.
+    pub site_rank: Option<u32>,
+    /// The average score of the subject on the site.
+    pub site_score: Option<f32>,
+    /// The category of the list the item is in, if known. Items parsed
+    /// from a single page don't know which list the page belongs to.
+    pub category: Option<Category>,
+    /// The state of the list the item is in, if known.
+    pub state: Option<State>,
+}
+
+/// A tag put on a subject by the community.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TagCount {
+    pub name: String,
+    /// Number of users who put the tag on the subject.
+    pub count: usize,
+}
+
+/// The community data shown on the page of a subject.
+#[derive(Clone, Debug, Default)]
+pub struct Subject {
+    pub id: Id,
+    /// The most used tags, from the most used one.
+    pub tags: Vec<TagCount>,
+    /// The number of votes for each rating, starting from 1.
+    pub ratings: [usize; MAX_RATING as usize],
+    /// The total number of votes.
+    pub votes: usize,
+    /// The average score, if there are enough votes to show one.
+    pub score: Option<f32>,
+}
+
+/// The information shown on the profile page of a user.
+#[derive(Clone, Debug, Default)]
+pub struct UserProfile {
+    pub username: String,
+    pub nickname: String,
+    /// The numeric id of the user, if shown on the page.
+    pub uid: Option<u32>,
+    pub joined_at: Option<Date>,
+    /// The number of items in each list of the user.
+    pub counts: HashMap<(Category, State), usize>,
+}
+
+/// A user in the friend list of another user.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Friend {
+    pub username: String,
+    pub nickname: String,
+}
+
+/// A list from which a different number of items than the profile
+/// shows has been got.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CountMismatch {
+    pub category: Category,
+    pub state: State,
+    /// The number of items according to the profile.
+    pub expected: usize,
+    pub actual: usize,
+}
+
+impl fmt::Display for CountMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "got {} items from {}/{}, but the profile shows {}",
+            self.actual, self.category, self.state, self.expected
+        )
+    }
+}
+
+impl UserProfile {
+    /// Returns the number of items in the list. Empty lists may be left
+    /// out of the profile, so a missing count means zero.
+    pub fn count(&self, category: Category, state: State) -> usize {
+        self.counts.get(&(category, state)).copied().unwrap_or(0)
+    }
+
+    /// Compares the number of items got from each of the lists with the
+    /// counts on the profile, to detect partial fetches.
+    pub fn check_counts(&self, lists: &[(Category, State)], items: &[Item]) -> Vec<CountMismatch> {
+        lists
+            .iter()
+            .map(|&(category, state)| CountMismatch {
+                category,
+                state,
+                expected: self.count(category, state),
+                actual: items
+                    .iter()
+                    .filter(|item| item.category == Some(category) && item.state == Some(state))
+                    .count(),
+            })
+            .filter(|mismatch| mismatch.actual != mismatch.expected)
+            .collect()
+    }
+}
+
+impl Item {
+    /// Returns the URL of the subject on bgm.tv.
+    pub fn subject_url(&self) -> String {
+        subject_url(DEFAULT_BASE_URL, self.id)
+    }
+}
+
+/// Returns the URL of the subject on the site at `base_url`, e.g. a
+/// mirror like `https://bangumi.tv`.
+pub fn subject_url(base_url: &str, id: Id) -> String {
+    format!("{}/subject/{}", base_url.trim_end_matches('/'), id)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Category, CountMismatch, Date, Item, State, UserProfile};
+
+    #[test]
+    fn test_parse_date() {
+        let date: Date = "2019-4-6".parse().unwrap();
+        assert_eq!(date, "2019-04-06".parse().unwrap());
+        assert!(date < "2019-10-1".parse().unwrap());
+        assert!("2019-13-1".parse::<Date>().is_err());
+        assert!("2019-4".parse::<Date>().is_err());
+        assert!("2019年4月6日".parse::<Date>().is_err());
+    }
+
+    #[test]
+    fn test_check_counts() {
+        let mut profile = UserProfile::default();
+        profile.counts.insert((Category::Anime, State::Collect), 2);
+        profile.counts.insert((Category::Anime, State::Wish), 1);
+        let items = [Category::Anime, Category::Anime, Category::Book].map(|category| Item {
+            category: Some(category),
+            state: Some(State::Collect),
+            ..Default::default()
+        });
+        let lists = [
+            (Category::Anime, State::Collect),
+            (Category::Anime, State::Wish),
+            (Category::Book, State::Collect),
+        ];
+        let mismatches = profile.check_counts(&lists, &items);
+        let mismatch = |category, state, expected, actual| CountMismatch {
+            category,
+            state,
+            expected,
+            actual,
+        };
+        assert_eq!(
+            mismatches,
+            [
+                mismatch(Category::Anime, State::Wish, 1, 0),
+                mismatch(Category::Book, State::Collect, 0, 1),
+            ]
+        );
+        assert_eq!(
+            mismatches[0].to_string(),
+            "got 0 items from anime/wish, but the profile shows 1"
+        );
+    }
 }
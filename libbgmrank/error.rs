@@ -0,0 +1,130 @@
+use crate::data::Id;
+use std::error;
+use std::fmt;
+use std::io;
+use strum::Display;
+
+/// The field of an item or page that failed to parse.
+#[derive(Clone, Copy, Debug, Display, PartialEq, Eq)]
+#[strum(serialize_all = "snake_case")]
+pub enum Field {
+    Id,
+    Title,
+    Rating,
+    Tags,
+    CollectedAt,
+    Nickname,
+}
+
+#[derive(Clone, Debug)]
+pub struct ParseError {
+    pub field: Field,
+    pub item: Option<Id>,
+    pub selector: &'static str,
+    /// The markup of the offending element, truncated if it's long.
+    pub snippet: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unexpected markup for {} at '{}'",
+            self.field, self.selector
+        )?;
+        if let Some(id) = self.item {
+            write!(f, " in item {}", id)?;
+        }
+        write!(f, ": {}", self.snippet)
+    }
+}
+
+impl error::Error for ParseError {}
+
+#[derive(Debug)]
+pub struct ParseDateError;
+
+impl fmt::Display for ParseDateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid date")
+    }
+}
+
+impl error::Error for ParseDateError {}
+
+#[derive(Debug)]
+pub enum Error {
+    #[cfg(any(feature = "blocking", feature = "async"))]
+    Network(reqwest::Error),
+    Io(io::Error),
+    Status {
+        url: String,
+        status: u16,
+    },
+    Parse(ParseError),
+    #[cfg(feature = "json")]
+    Json(serde_json::Error),
+    UserNotFound(String),
+    SubjectNotFound(Id),
+    InvalidCookie,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(any(feature = "blocking", feature = "async"))]
+            Error::Network(e) => write!(f, "network error: {}", e),
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::Status { url, status } => write!(f, "HTTP {} from {}", status, url),
+            Error::Parse(e) => write!(f, "parse error: {}", e),
+            #[cfg(feature = "json")]
+            Error::Json(e) => write!(f, "invalid JSON: {}", e),
+            Error::UserNotFound(user) => write!(f, "user '{}' not found", user),
+            Error::SubjectNotFound(id) => write!(f, "subject {} not found", id),
+            Error::InvalidCookie => write!(f, "invalid session cookie"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            #[cfg(any(feature = "blocking", feature = "async"))]
+            Error::Network(e) => Some(e),
+            Error::Io(e) => Some(e),
+            Error::Parse(e) => Some(e),
+            #[cfg(feature = "json")]
+            Error::Json(e) => Some(e),
+            Error::Status { .. }
+            | Error::UserNotFound(_)
+            | Error::SubjectNotFound(_)
+            | Error::InvalidCookie => None,
+        }
+    }
+}
+
+#[cfg(any(feature = "blocking", feature = "async"))]
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Network(e)
+    }
+}
+
+#[cfg(feature = "json")]
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(e: ParseError) -> Self {
+        Error::Parse(e)
+    }
+}
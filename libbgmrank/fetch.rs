@@ -1,41 +1,1351 @@
-use crate::data::{Category, Item, State};
-use crate::parser;
+use crate::auth::SessionCookie;
+use crate::cache::{PageCache, PageKey, DEFAULT_CACHE_TTL};
+use crate::data::{self, Friend, Id, Item, Subject, UserProfile, DEFAULT_BASE_URL};
+use crate::error::{Error, ParseError};
+use crate::json;
+use crate::parser::{self, ParseMode};
+use crate::progress::Progress;
+use crate::retry::RetryPolicy;
+use crate::source;
 use html5ever::tendril::stream::TendrilSink;
-use kuchiki::NodeRef;
-use reqwest::blocking::Client;
-use std::error::Error;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
+use std::path::PathBuf;
+use std::time::Duration;
+use strum::{EnumIter, EnumString, IntoStaticStr};
+#[cfg(feature = "blocking")]
+use {
+    crate::data::{Category, CountMismatch, State},
+    crate::source::CollectionSource,
+    reqwest::blocking::Client,
+    reqwest::header::COOKIE,
+    reqwest::Proxy,
+    std::cell::RefCell,
+    std::sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    std::sync::Mutex,
+    std::thread,
+    std::time::Instant,
+};
 
-const ITEMS_PER_PAGE: usize = 24;
+pub const DEFAULT_API_BASE_URL: &str = "https://api.bgm.tv";
+const DEFAULT_USER_AGENT: &str = concat!("libbgmrank/", env!("CARGO_PKG_VERSION"));
+/// Number of collections requested per page from the API.
+const API_PAGE_SIZE: usize = 50;
 
-fn fetch_page(client: &Client, url: &str) -> Result<NodeRef, Box<dyn Error>> {
-    client.get(url).send()?;
-    let mut resp = client.get(url).send()?;
-    Ok(kuchiki::parse_html().from_utf8().read_from(&mut resp)?)
+/// Where a fetcher reads collections from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, EnumIter, EnumString, IntoStaticStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum Source {
+    /// Scrapes the list pages of the site.
+    #[default]
+    Html,
+    /// Reads the collections from the Bangumi v0 API. Only public
+    /// collections are available this way.
+    Api,
 }
 
+pub struct FetcherBuilder {
+    source: Source,
+    base_url: String,
+    api_base_url: String,
+    pub(crate) user_agent: String,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) proxy: Option<String>,
+    #[cfg(feature = "blocking")]
+    client: Option<Client>,
+    #[cfg(feature = "async")]
+    pub(crate) async_client: Option<reqwest::Client>,
+    retry_policy: RetryPolicy,
+    min_interval: Duration,
+    cache: Option<PageCache>,
+    concurrency: usize,
+    cookie: Option<SessionCookie>,
+    parse_mode: ParseMode,
+}
+
+impl Default for FetcherBuilder {
+    fn default() -> Self {
+        FetcherBuilder {
+            source: Source::default(),
+            base_url: DEFAULT_BASE_URL.to_string(),
+            api_base_url: DEFAULT_API_BASE_URL.to_string(),
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            timeout: None,
+            proxy: None,
+            #[cfg(feature = "blocking")]
+            client: None,
+            #[cfg(feature = "async")]
+            async_client: None,
+            retry_policy: RetryPolicy::default(),
+            min_interval: Duration::ZERO,
+            cache: None,
+            concurrency: 1,
+            cookie: None,
+            parse_mode: ParseMode::default(),
+        }
+    }
+}
+
+impl FetcherBuilder {
+    pub fn source(mut self, source: Source) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Sets the site to fetch from, e.g. `https://bangumi.tv`,
+    /// `https://chii.in`, or a local server for testing.
+    pub fn base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    /// Sets the API server used with `Source::Api`.
+    pub fn api_base_url(mut self, api_base_url: impl Into<String>) -> Self {
+        self.api_base_url = api_base_url.into();
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn proxy(mut self, proxy: impl Into<String>) -> Self {
+        self.proxy = Some(proxy.into());
+        self
+    }
+
+    /// Uses an existing client for all requests. The user agent, timeout
+    /// and proxy settings are ignored in this case.
+    #[cfg(feature = "blocking")]
+    pub fn client(mut self, client: Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Uses an existing async client for all requests of an
+    /// `AsyncFetcher`. The user agent, timeout and proxy settings are
+    /// ignored in this case.
+    #[cfg(feature = "async")]
+    pub fn async_client(mut self, client: reqwest::Client) -> Self {
+        self.async_client = Some(client);
+        self
+    }
+
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Sets the minimum delay between the starts of two requests.
+    pub fn min_interval(mut self, min_interval: Duration) -> Self {
+        self.min_interval = min_interval;
+        self
+    }
+
+    /// Serves list pages from the given cache when possible, and stores
+    /// fetched pages into it on a best-effort basis. Responses from the
+    /// API are not cached.
+    pub fn cache(mut self, cache: PageCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Sets the maximum number of pages fetched in parallel by
+    /// `Fetcher::get_all_items`. Requests still honor `min_interval`.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Sends the cookies of a logged-in session with every request, so
+    /// that private collections are visible.
+    pub fn cookie(mut self, cookie: SessionCookie) -> Self {
+        self.cookie = Some(cookie);
+        self
+    }
+
+    /// Sets how to deal with items on list pages which fail to parse. In
+    /// lenient mode they are skipped and reported to the observer.
+    pub fn parse_mode(mut self, parse_mode: ParseMode) -> Self {
+        self.parse_mode = parse_mode;
+        self
+    }
+
+    /// Applies the settings shared by the command line tools. The session
+    /// cookie is read from `cookie_file`, or from `$BGMRANK_COOKIE` if it
+    /// isn't given.
+    pub fn options(mut self, options: &FetchOptions) -> Result<Self, Error> {
+        self = self
+            .source(options.source)
+            .concurrency(options.jobs)
+            .min_interval(options.interval);
+        if options.lenient {
+            self = self.parse_mode(ParseMode::Lenient);
+        }
+        if options.cache {
+            if let Some(dir) = PageCache::default_dir() {
+                self = self.cache(PageCache::new(dir, DEFAULT_CACHE_TTL).refresh(options.refresh));
+            }
+        }
+        let cookie = match &options.cookie_file {
+            Some(path) => Some(SessionCookie::from_file(path)?),
+            None => SessionCookie::from_env().transpose()?,
+        };
+        if let Some(cookie) = cookie {
+            self = self.cookie(cookie);
+        }
+        Ok(self)
+    }
+
+    #[cfg(feature = "blocking")]
+    pub fn build(mut self) -> Result<Fetcher, Error> {
+        let client = match self.client.take() {
+            Some(client) => client,
+            None => {
+                let mut builder = Client::builder().user_agent(&self.user_agent);
+                if let Some(timeout) = self.timeout {
+                    builder = builder.timeout(timeout);
+                }
+                if let Some(proxy) = &self.proxy {
+                    builder = builder.proxy(Proxy::all(proxy)?);
+                }
+                builder.build()?
+            }
+        };
+        Ok(Fetcher {
+            client,
+            last_request: Mutex::new(None),
+            concurrency: self.concurrency,
+            config: self.into_config(),
+        })
+    }
+
+    /// Takes the settings shared by `Fetcher` and `AsyncFetcher`.
+    pub(crate) fn into_config(self) -> FetchConfig {
+        FetchConfig {
+            source: self.source,
+            base_url: self.base_url.trim_end_matches('/').to_string(),
+            api_base_url: self.api_base_url.trim_end_matches('/').to_string(),
+            retry_policy: self.retry_policy,
+            min_interval: self.min_interval,
+            cache: self
+                .cache
+                .map(|cache| cache.authenticated(self.cookie.is_some())),
+            cookie: self.cookie,
+            parse_mode: self.parse_mode,
+        }
+    }
+}
+
+/// Settings of the fetcher which the command line tools let users
+/// choose, applied with `FetcherBuilder::options`.
+#[derive(Clone, Debug, Default)]
+pub struct FetchOptions {
+    pub source: Source,
+    /// Number of pages to fetch in parallel.
+    pub jobs: usize,
+    /// Minimum delay between the starts of two requests.
+    pub interval: Duration,
+    /// Whether to skip items which fail to parse.
+    pub lenient: bool,
+    /// Whether to use the page cache in `PageCache::default_dir`.
+    pub cache: bool,
+    /// Whether to refetch all pages and update the cache.
+    pub refresh: bool,
+    /// The file to read the session cookie from.
+    pub cookie_file: Option<PathBuf>,
+}
+
+/// The settings shared by `Fetcher` and `AsyncFetcher`, along with the
+/// parts of fetching which don't send requests, so that the two only
+/// differ in how they send requests and wait.
+pub(crate) struct FetchConfig {
+    pub source: Source,
+    pub base_url: String,
+    pub api_base_url: String,
+    pub retry_policy: RetryPolicy,
+    pub min_interval: Duration,
+    pub cache: Option<PageCache>,
+    pub cookie: Option<SessionCookie>,
+    pub parse_mode: ParseMode,
+}
+
+impl FetchConfig {
+    pub fn subject_url(&self, id: Id) -> String {
+        data::subject_url(&self.base_url, id)
+    }
+
+    pub fn profile_url(&self, username: &str) -> String {
+        format!("{}/user/{}", self.base_url, username)
+    }
+
+    pub fn friends_url(&self, username: &str) -> String {
+        format!("{}/user/{}/friends", self.base_url, username)
+    }
+
+    /// Returns the URL of the list page, on the site or the API.
+    pub fn list_url(&self, key: &PageKey) -> String {
+        match self.source {
+            Source::Html => page_url(&self.base_url, key),
+            Source::Api => api_url(&self.api_base_url, key),
+        }
+    }
+
+    /// Decides whether to retry after the given attempt failed, from the
+    /// status and headers of the response or the error of the request.
+    /// Returns the delay before the next attempt if so.
+    pub fn retry_delay(
+        &self,
+        attempt: u32,
+        response: Result<(StatusCode, &HeaderMap), &reqwest::Error>,
+    ) -> Option<Duration> {
+        let retry_after = match response {
+            Ok((status, headers)) if is_transient_status(status) => get_retry_after(headers),
+            Err(e) if is_transient_error(e) => None,
+            _ => return None,
+        };
+        if attempt >= self.retry_policy.max_attempts {
+            return None;
+        }
+        Some(self.retry_policy.backoff(attempt, retry_after))
+    }
+
+    /// Returns the list page from the cache, if it's there.
+    pub fn cached_page(&self, key: &PageKey) -> Result<Option<ParsedPage>, Error> {
+        if self.source != Source::Html {
+            return Ok(None);
+        }
+        match self.cache.as_ref().and_then(|cache| cache.get(key)) {
+            Some(html) => Ok(Some(parse_list_page(key, Some(&html), self.parse_mode)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Parses a fetched list page, where `None` means the page doesn't
+    /// exist, and stores it into the cache.
+    pub fn parse_page(&self, key: &PageKey, body: Option<&str>) -> Result<ParsedPage, Error> {
+        match self.source {
+            Source::Api => parse_api_page(key, body),
+            Source::Html => {
+                let page = parse_list_page(key, body, self.parse_mode)?;
+                if let (Some(cache), Some(html)) = (&self.cache, body) {
+                    // The page is already fetched, so a cache which can't
+                    // be written shouldn't fail the fetch.
+                    let _ = cache.put(key, html);
+                }
+                Ok(page)
+            }
+        }
+    }
+}
+
+/// Reports the skipped items and the parsed page to the observer.
+pub(crate) fn report_page(
+    key: &PageKey,
+    mut page: ParsedPage,
+    mut notify: impl FnMut(Progress),
+) -> ParsedPage {
+    for error in page.skipped.drain(..) {
+        notify(Progress::ItemSkipped {
+            category: key.category,
+            state: key.state,
+            page: key.page,
+            error,
+        });
+    }
+    notify(Progress::PageParsed {
+        category: key.category,
+        state: key.state,
+        page: key.page,
+        items: page.items.len(),
+    });
+    page
+}
+
+/// Follows the number of pages of a list while fetching them one by one.
+#[derive(Default)]
+pub(crate) struct PageCount(usize);
+
+impl PageCount {
+    /// Updates the count from the given page, reporting it when it grows,
+    /// and returns the number of the next page to fetch, if any.
+    pub fn next_page(
+        &mut self,
+        key: &PageKey,
+        page: &ParsedPage,
+        mut notify: impl FnMut(Progress),
+    ) -> Option<usize> {
+        if page.page_count > self.0 {
+            self.0 = page.page_count;
+            notify(Progress::PageCountKnown {
+                category: key.category,
+                state: key.state,
+                pages: self.0,
+                total_items: page.total_items,
+            });
+        }
+        Some(key.page + 1).filter(|_| key.page < self.0)
+    }
+}
+
+#[cfg(feature = "blocking")]
+pub struct Fetcher {
+    client: Client,
+    last_request: Mutex<Option<Instant>>,
+    concurrency: usize,
+    config: FetchConfig,
+}
+
+#[cfg(feature = "blocking")]
+impl Fetcher {
+    pub fn new() -> Result<Self, Error> {
+        Self::builder().build()
+    }
+
+    pub fn builder() -> FetcherBuilder {
+        FetcherBuilder::default()
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.config.base_url
+    }
+
+    /// Returns the URL of the subject on the configured site.
+    pub fn subject_url(&self, id: Id) -> String {
+        self.config.subject_url(id)
+    }
+
+    /// Blocks until at least `min_interval` has passed since the previous
+    /// request was started.
+    fn wait_for_turn(&self) {
+        let mut last_request = self.last_request.lock().unwrap();
+        if let Some(last) = *last_request {
+            let elapsed = last.elapsed();
+            if elapsed < self.config.min_interval {
+                thread::sleep(self.config.min_interval - elapsed);
+            }
+        }
+        *last_request = Some(Instant::now());
+    }
+
+    /// Fetches `url`, and returns its content, or `None` if the server
+    /// responds with 404. Calls `on_retry` with the next attempt and the
+    /// delay before it when retrying.
+    fn fetch_url(
+        &self,
+        url: &str,
+        on_retry: impl Fn(u32, Duration),
+    ) -> Result<Option<String>, Error> {
+        let mut attempt = 1;
+        let resp = loop {
+            self.wait_for_turn();
+            let mut request = self.client.get(url);
+            if let Some(cookie) = &self.config.cookie {
+                request = request.header(COOKIE, cookie.header_value());
+            }
+            let result = request.send();
+            let response = result.as_ref().map(|resp| (resp.status(), resp.headers()));
+            let delay = match self.config.retry_delay(attempt, response) {
+                Some(delay) => delay,
+                None => break result?,
+            };
+            attempt += 1;
+            on_retry(attempt, delay);
+            thread::sleep(delay);
+        };
+        if !check_status(url, resp.status())? {
+            return Ok(None);
+        }
+        Ok(Some(resp.text()?))
+    }
+
+    /// Fetches the page of the subject and parses the community tags and
+    /// ratings on it.
+    pub fn get_subject(&self, id: Id) -> Result<Subject, Error> {
+        let html = self.fetch_url(&self.subject_url(id), |_, _| {})?;
+        parse_subject_page(id, html.as_deref())
+    }
+
+    /// Fetches the profile page of the user, which includes the number
+    /// of items in each list.
+    pub fn get_user_profile(&self, username: &str) -> Result<UserProfile, Error> {
+        let html = self.fetch_url(&self.config.profile_url(username), |_, _| {})?;
+        parse_profile_page(username, html.as_deref())
+    }
+
+    /// Fetches the friend list of the user.
+    pub fn get_friends(&self, username: &str) -> Result<Vec<Friend>, Error> {
+        let html = self.fetch_url(&self.config.friends_url(username), |_, _| {})?;
+        parse_friends_page(username, html.as_deref())
+    }
+
+    /// Fetches the profile of the user, and returns the lists whose number
+    /// of items differs from the count on it, which usually means pages
+    /// failed to parse or the list changed while fetching.
+    pub fn check_counts(
+        &self,
+        username: &str,
+        lists: &[(Category, State)],
+        items: &[Item],
+    ) -> Result<Vec<CountMismatch>, Error> {
+        Ok(self.get_user_profile(username)?.check_counts(lists, items))
+    }
+
+    /// Fetches and parses a list page, reporting the skipped items.
+    fn get_page_items(
+        &self,
+        key: &PageKey,
+        notify: &impl Fn(Progress),
+    ) -> Result<ParsedPage, Error> {
+        notify(Progress::PageStarted {
+            category: key.category,
+            state: key.state,
+            page: key.page,
+        });
+        let page = match self.config.cached_page(key)? {
+            Some(page) => page,
+            None => {
+                let body = self.fetch_url(&self.config.list_url(key), |attempt, delay| {
+                    notify(Progress::Retrying {
+                        category: key.category,
+                        state: key.state,
+                        page: key.page,
+                        attempt,
+                        delay,
+                    })
+                })?;
+                self.config.parse_page(key, body.as_deref())?
+            }
+        };
+        Ok(report_page(key, page, notify))
+    }
+
+    /// Fetches all items of the list, reporting progress to the observer.
+    pub fn get_items(
+        &self,
+        username: &str,
+        category: Category,
+        state: State,
+        observer: impl FnMut(Progress),
+    ) -> Result<Vec<Item>, Error> {
+        let observer = RefCell::new(observer);
+        let notify = |progress| (observer.borrow_mut())(progress);
+        let mut result = vec![];
+        let mut page_count = PageCount::default();
+        let mut next = Some(1);
+        while let Some(page) = next {
+            let key = PageKey {
+                username,
+                category,
+                state,
+                page,
+            };
+            let parsed = self.get_page_items(&key, &notify)?;
+            next = page_count.next_page(&key, &parsed, &notify);
+            result.extend(parsed.items);
+        }
+        notify(Progress::Finished {
+            items: result.len(),
+        });
+        Ok(result)
+    }
+
+    /// Fetches the items of all the given lists, using up to
+    /// `concurrency` requests in parallel, and reporting progress to the
+    /// observer.
+    ///
+    /// The first pages of all lists are fetched first to find out how
+    /// many pages each list has, then all the remaining pages are
+    /// fetched. Items are returned in the order of the lists, and in
+    /// the order they appear on the site within each list.
+    pub fn get_all_items(
+        &self,
+        username: &str,
+        lists: &[(Category, State)],
+        observer: impl FnMut(Progress) + Send,
+    ) -> Result<Vec<Item>, Error> {
+        let observer = Mutex::new(observer);
+        let notify = |progress| (observer.lock().unwrap())(progress);
+        let fetch = |category, state, page| {
+            let key = PageKey {
+                username,
+                category,
+                state,
+                page,
+            };
+            self.get_page_items(&key, &notify)
+        };
+        let first_pages = self.run_concurrently(lists.len(), |i| {
+            let (category, state) = lists[i];
+            let page = fetch(category, state, 1)?;
+            notify(Progress::PageCountKnown {
+                category,
+                state,
+                pages: page.page_count,
+                total_items: page.total_items,
+            });
+            Ok(page)
+        })?;
+        let rest: Vec<_> = first_pages
+            .iter()
+            .enumerate()
+            .flat_map(|(i, first)| (2..=first.page_count).map(move |page| (i, page)))
+            .collect();
+        let mut rest_pages = self
+            .run_concurrently(rest.len(), |i| {
+                let (list, page) = rest[i];
+                let (category, state) = lists[list];
+                fetch(category, state, page)
+            })?
+            .into_iter();
+        let mut result = vec![];
+        for first in first_pages {
+            result.extend(first.items);
+            for page in rest_pages.by_ref().take(first.page_count - 1) {
+                result.extend(page.items);
+            }
+        }
+        notify(Progress::Finished {
+            items: result.len(),
+        });
+        Ok(result)
+    }
+
+    /// Runs `task` for every index in `0..count` on up to `concurrency`
+    /// threads, and returns the results in the order of the indices.
+    fn run_concurrently<T: Send>(
+        &self,
+        count: usize,
+        task: impl Fn(usize) -> Result<T, Error> + Sync,
+    ) -> Result<Vec<T>, Error> {
+        let next = AtomicUsize::new(0);
+        let failed = AtomicBool::new(false);
+        let results = Mutex::new((0..count).map(|_| None).collect::<Vec<_>>());
+        thread::scope(|scope| {
+            for _ in 0..self.concurrency.min(count) {
+                scope.spawn(|| {
+                    while !failed.load(Ordering::SeqCst) {
+                        let i = next.fetch_add(1, Ordering::SeqCst);
+                        if i >= count {
+                            break;
+                        }
+                        let result = task(i);
+                        if result.is_err() {
+                            failed.store(true, Ordering::SeqCst);
+                        }
+                        results.lock().unwrap()[i] = Some(result);
+                    }
+                });
+            }
+        });
+        // Tasks which never ran because of an earlier failure are left
+        // as None, and there is always an error before them.
+        results
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+}
+
+#[cfg(feature = "blocking")]
+impl CollectionSource for Fetcher {
+    fn get_items(
+        &self,
+        username: &str,
+        category: Category,
+        state: State,
+    ) -> Result<Vec<Item>, Error> {
+        Fetcher::get_items(self, username, category, state, |_| {})
+    }
+
+    fn get_all_items(
+        &self,
+        username: &str,
+        lists: &[(Category, State)],
+    ) -> Result<Vec<Item>, Error> {
+        Fetcher::get_all_items(self, username, lists, |_| {})
+    }
+}
+
+fn page_url(base_url: &str, key: &PageKey) -> String {
+    let category_str: &str = key.category.into();
+    let state_str: &str = key.state.into();
+    format!(
+        "{}/{}/list/{}/{}?page={}",
+        base_url, category_str, key.username, state_str, key.page
+    )
+}
+
+fn api_url(api_base_url: &str, key: &PageKey) -> String {
+    format!(
+        "{}/v0/users/{}/collections?subject_type={}&type={}&limit={}&offset={}",
+        api_base_url,
+        key.username,
+        json::subject_type(key.category),
+        json::collection_type(key.state),
+        API_PAGE_SIZE,
+        (key.page - 1) * API_PAGE_SIZE
+    )
+}
+
+/// Returns `Ok(false)` for 404, and an error for other unsuccessful
+/// statuses.
+pub(crate) fn check_status(url: &str, status: StatusCode) -> Result<bool, Error> {
+    if status == StatusCode::NOT_FOUND {
+        Ok(false)
+    } else if !status.is_success() {
+        Err(Error::Status {
+            url: url.to_string(),
+            status: status.as_u16(),
+        })
+    } else {
+        Ok(true)
+    }
+}
+
+fn is_transient_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+fn is_transient_error(error: &reqwest::Error) -> bool {
+    error.is_timeout() || error.is_connect()
+}
+
+/// Reads the `Retry-After` header. Only the delay-seconds form is
+/// supported, which is what Bangumi sends.
+fn get_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?;
+    value.trim().parse().ok().map(Duration::from_secs)
+}
+
+/// A parsed list page.
+pub(crate) struct ParsedPage {
+    pub items: Vec<Item>,
+    /// Errors of the items skipped in lenient mode.
+    pub skipped: Vec<ParseError>,
+    /// Number of pages of the list.
+    pub page_count: usize,
+    /// Number of items in the list, if the page shows it.
+    pub total_items: Option<usize>,
+}
+
+/// Parses a fetched list page, where `None` means the page doesn't
+/// exist.
+fn parse_list_page(
+    key: &PageKey,
+    html: Option<&str>,
+    mode: ParseMode,
+) -> Result<ParsedPage, Error> {
+    let doc = match html.map(|html| kuchiki::parse_html().one(html)) {
+        Some(doc) if parser::has_item_list(&doc) => doc,
+        _ => return Err(Error::UserNotFound(key.username.to_string())),
+    };
+    let info = parser::get_page_info(&doc);
+    let (mut items, skipped) = parser::get_all_items(doc, mode)?;
+    source::set_list(&mut items, key.category, key.state);
+    let page_count = match info.page_count {
+        Some(page_count) => page_count,
+        // Without a pager the list should fit in a single page, but check
+        // the total in the header as well in case the pager markup changes.
+        None => match info.total_items {
+            // Count the skipped items as well, as they take up the page too.
+            Some(total) if key.page == 1 && !items.is_empty() => {
+                total.div_ceil(items.len() + skipped.len())
+            }
+            _ => key.page,
+        },
+    };
+    Ok(ParsedPage {
+        items,
+        skipped,
+        page_count,
+        total_items: info.total_items,
+    })
+}
+
+/// Parses a fetched subject page, where `None` means the page doesn't
+/// exist.
+pub(crate) fn parse_subject_page(id: Id, html: Option<&str>) -> Result<Subject, Error> {
+    match html.map(|html| kuchiki::parse_html().one(html)) {
+        Some(doc) if parser::has_subject(&doc) => Ok(parser::get_subject(&doc)?),
+        _ => Err(Error::SubjectNotFound(id)),
+    }
+}
+
+/// Parses a fetched profile page, where `None` means the page doesn't
+/// exist.
+pub(crate) fn parse_profile_page(username: &str, html: Option<&str>) -> Result<UserProfile, Error> {
+    match html.map(|html| kuchiki::parse_html().one(html)) {
+        Some(doc) if parser::has_user_profile(&doc) => {
+            Ok(parser::get_user_profile(&doc, username)?)
+        }
+        _ => Err(Error::UserNotFound(username.to_string())),
+    }
+}
+
+/// Parses a fetched friends page, where `None` means the page doesn't
+/// exist.
+pub(crate) fn parse_friends_page(username: &str, html: Option<&str>) -> Result<Vec<Friend>, Error> {
+    match html.map(|html| kuchiki::parse_html().one(html)) {
+        // The page shows the header of the profile as well.
+        Some(doc) if parser::has_user_profile(&doc) => Ok(parser::get_friends(&doc)),
+        _ => Err(Error::UserNotFound(username.to_string())),
+    }
+}
+
+/// Parses a page of collections from the API, where `None` means the
+/// user doesn't exist.
+fn parse_api_page(key: &PageKey, json: Option<&str>) -> Result<ParsedPage, Error> {
+    let json = json.ok_or_else(|| Error::UserNotFound(key.username.to_string()))?;
+    let (items, total) = json::parse_collection_page(json)?;
+    Ok(ParsedPage {
+        items,
+        skipped: vec![],
+        page_count: total.div_ceil(API_PAGE_SIZE).max(1),
+        total_items: Some(total),
+    })
+}
+
+/// Fetches items from bgm.tv with the default settings.
+#[cfg(feature = "blocking")]
 pub fn get_items(
     username: &str,
     category: Category,
     state: State,
-    callback: impl Fn(usize),
-) -> Vec<Item> {
-    let category_str: &str = category.into();
-    let state_str: &str = state.into();
-    let client = Client::new();
-    let mut result = vec![];
-    for page in 1.. {
-        callback(page);
-        let url = format!(
-            "https://bgm.tv/{}/list/{}/{}?page={}",
-            category_str, username, state_str, page
+    observer: impl FnMut(Progress),
+) -> Result<Vec<Item>, Error> {
+    Fetcher::new()?.get_items(username, category, state, observer)
+}
+
+#[cfg(all(test, feature = "blocking"))]
+mod test {
+    use super::{Fetcher, Source};
+    use crate::auth::SessionCookie;
+    use crate::cache::PageCache;
+    use crate::data::{Category, State};
+    use crate::error::{Error, Field};
+    use crate::parser::ParseMode;
+    use crate::progress::Progress;
+    use crate::retry::RetryPolicy;
+    use crate::test_server::{collection_page, list_page, Response, TestServer};
+    use std::time::{Duration, Instant};
+
+    fn quick_retry(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(10),
+            jitter: true,
+        }
+    }
+
+    #[test]
+    fn test_custom_base_url() {
+        let server = TestServer::start(|target, _| {
+            if target.starts_with("/anime/list/someone/collect?") {
+                Response::ok(list_page(1..=3))
+            } else if target.starts_with("/book/list/someone/do?") {
+                Response::ok(list_page(4..=5))
+            } else {
+                Response::status(404)
+            }
+        });
+        let fetcher = Fetcher::builder()
+            .base_url(format!("{}/", server.base_url))
+            .build()
+            .unwrap();
+        let items = fetcher
+            .get_items("someone", Category::Anime, State::Collect, |_| {})
+            .unwrap();
+        assert_eq!(items.iter().map(|i| i.id).collect::<Vec<_>>(), [1, 2, 3]);
+        let items = fetcher
+            .get_items("someone", Category::Book, State::Do, |_| {})
+            .unwrap();
+        assert_eq!(items.iter().map(|i| i.id).collect::<Vec<_>>(), [4, 5]);
+        assert_eq!(items[0].category, Some(Category::Book));
+        assert_eq!(items[0].state, Some(State::Do));
+        let subject_url = format!("{}/subject/4", server.base_url);
+        assert_eq!(fetcher.subject_url(items[0].id), subject_url);
+
+        let result = fetcher.get_items("nobody", Category::Anime, State::Collect, |_| {});
+        assert!(matches!(result, Err(Error::UserNotFound(user)) if user == "nobody"));
+    }
+
+    #[test]
+    fn test_retry_transient_errors() {
+        let server = TestServer::start(|_, index| match index {
+            0 => Response::status(503),
+            1 => Response::status(429),
+            _ => Response::ok(list_page(1..=2)),
+        });
+        let fetcher = Fetcher::builder()
+            .base_url(&server.base_url)
+            .retry_policy(quick_retry(3))
+            .build()
+            .unwrap();
+        let mut retries = vec![];
+        let items = fetcher
+            .get_items("someone", Category::Anime, State::Collect, |event| {
+                if let Progress::Retrying { page, attempt, .. } = event {
+                    retries.push((page, attempt));
+                }
+            })
+            .unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(server.requests(), 3);
+        assert_eq!(retries, [(1, 2), (1, 3)]);
+    }
+
+    #[test]
+    fn test_retry_gives_up() {
+        let server = TestServer::start(|_, _| Response::status(502));
+        let fetcher = Fetcher::builder()
+            .base_url(&server.base_url)
+            .retry_policy(quick_retry(3))
+            .build()
+            .unwrap();
+        let result = fetcher.get_items("someone", Category::Anime, State::Collect, |_| {});
+        assert!(matches!(result, Err(Error::Status { status: 502, .. })));
+
+        // Client errors other than 429 are not retried, so this would
+        // take far longer than a second otherwise.
+        let server = TestServer::start(|_, _| Response::status(403));
+        let fetcher = Fetcher::builder()
+            .base_url(&server.base_url)
+            .retry_policy(RetryPolicy {
+                max_attempts: 3,
+                initial_backoff: Duration::from_secs(5),
+                ..Default::default()
+            })
+            .build()
+            .unwrap();
+        let start = Instant::now();
+        let result = fetcher.get_items("someone", Category::Anime, State::Collect, |_| {});
+        assert!(matches!(result, Err(Error::Status { status: 403, .. })));
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let server = TestServer::start(|_, index| match index {
+            0 => Response {
+                headers: vec![("Retry-After", "1".to_string())],
+                ..Response::status(429)
+            },
+            _ => Response::ok(list_page(1..=2)),
+        });
+        let fetcher = Fetcher::builder()
+            .base_url(&server.base_url)
+            .retry_policy(quick_retry(2))
+            .build()
+            .unwrap();
+        let start = Instant::now();
+        fetcher
+            .get_items("someone", Category::Anime, State::Collect, |_| {})
+            .unwrap();
+        assert!(start.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_min_interval() {
+        let server = TestServer::start(|_, _| Response::ok(list_page(1..=2)));
+        let fetcher = Fetcher::builder()
+            .base_url(&server.base_url)
+            .min_interval(Duration::from_millis(100))
+            .build()
+            .unwrap();
+        let start = Instant::now();
+        for _ in 0..3 {
+            fetcher
+                .get_items("someone", Category::Anime, State::Collect, |_| {})
+                .unwrap();
+        }
+        // At least two intervals have to be waited between the three
+        // requests.
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn test_one_request_per_page() {
+        // A list of exactly 48 items shouldn't need a request for a third
+        // page, and the page size is not assumed.
+        let server = TestServer::start(|target, _| {
+            let page: u32 = target.rsplit('=').next().unwrap().parse().unwrap();
+            let pager = r#"<div id="multipage"><a href="?page=2">2</a></div>"#;
+            let start = (page - 1) * 24;
+            Response::ok(pager.to_string() + &list_page(start..start + 24))
+        });
+        let fetcher = Fetcher::builder()
+            .base_url(&server.base_url)
+            .build()
+            .unwrap();
+        let mut events = vec![];
+        let items = fetcher
+            .get_items("someone", Category::Anime, State::Collect, |event| {
+                events.push(event)
+            })
+            .unwrap();
+        assert_eq!(items.len(), 48);
+        assert_eq!(server.requests(), 2);
+        let events: Vec<_> = events
+            .into_iter()
+            .map(|event| match event {
+                Progress::PageStarted { page, .. } => format!("started {}", page),
+                Progress::PageParsed { page, items, .. } => format!("parsed {} {}", page, items),
+                Progress::PageCountKnown { pages, .. } => format!("pages {}", pages),
+                Progress::Finished { items } => format!("finished {}", items),
+                Progress::Retrying { .. } | Progress::ItemSkipped { .. } => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            events,
+            [
+                "started 1",
+                "parsed 1 24",
+                "pages 2",
+                "started 2",
+                "parsed 2 24",
+                "finished 48"
+            ]
         );
-        let doc = fetch_page(&client, &url).unwrap();
-        let items = parser::get_all_items(doc);
-        let count = items.len();
-        result.extend(items);
-        if count < ITEMS_PER_PAGE {
-            break;
+    }
+
+    #[test]
+    fn test_page_count_from_header() {
+        let server = TestServer::start(|target, _| {
+            let page: u32 = target.rsplit('=').next().unwrap().parse().unwrap();
+            let header = r#"<ul class="navSubTabs"><a class="focus">看过 (30)</a></ul>"#;
+            let ids = if page == 1 { 0..20 } else { 20..30 };
+            Response::ok(header.to_string() + &list_page(ids))
+        });
+        let fetcher = Fetcher::builder()
+            .base_url(&server.base_url)
+            .build()
+            .unwrap();
+        let mut total_items = None;
+        let items = fetcher
+            .get_items("someone", Category::Anime, State::Collect, |event| {
+                if let Progress::PageCountKnown { total_items: t, .. } = event {
+                    total_items = t;
+                }
+            })
+            .unwrap();
+        assert_eq!(items.len(), 30);
+        assert_eq!(total_items, Some(30));
+        assert_eq!(server.requests(), 2);
+
+        // Items skipped in lenient mode still count towards the page size.
+        let server = TestServer::start(|target, _| {
+            let page: u32 = target.rsplit('=').next().unwrap().parse().unwrap();
+            let header = r#"<ul class="navSubTabs"><a class="focus">看过 (40)</a></ul>"#;
+            let bad_item = r#"<li id="item_99"><h3><span>Item 99</span></h3></li>"#;
+            let html = match page {
+                1 => list_page(0..19).replace("</ul>", &(bad_item.to_string() + "</ul>")),
+                _ => list_page(20..40),
+            };
+            Response::ok(header.to_string() + &html)
+        });
+        let fetcher = Fetcher::builder()
+            .base_url(&server.base_url)
+            .parse_mode(ParseMode::Lenient)
+            .build()
+            .unwrap();
+        let items = fetcher
+            .get_items("someone", Category::Anime, State::Collect, |_| {})
+            .unwrap();
+        assert_eq!(items.len(), 39);
+        assert_eq!(server.requests(), 2);
+    }
+
+    #[test]
+    fn test_page_cache() {
+        let dir = std::env::temp_dir().join(format!("bgmrank-fetch-{}", std::process::id()));
+        let server = TestServer::start(|_, _| Response::ok(list_page(1..=2)));
+        let make_fetcher = |cache| {
+            Fetcher::builder()
+                .base_url(&server.base_url)
+                .cache(cache)
+                .build()
+                .unwrap()
+        };
+        let fetch = |fetcher: &Fetcher| {
+            fetcher
+                .get_items("someone", Category::Anime, State::Collect, |_| {})
+                .unwrap()
+                .len()
+        };
+
+        let fetcher = make_fetcher(PageCache::new(&dir, Duration::from_secs(60)));
+        assert_eq!(fetch(&fetcher), 2);
+        assert_eq!(fetch(&fetcher), 2);
+        assert_eq!(server.requests(), 1);
+
+        let fetcher = make_fetcher(PageCache::new(&dir, Duration::from_secs(60)).refresh(true));
+        assert_eq!(fetch(&fetcher), 2);
+        assert_eq!(server.requests(), 2);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_unwritable_page_cache() {
+        // A file in place of the cache directory makes every write fail.
+        let dir = std::env::temp_dir().join(format!("bgmrank-no-cache-{}", std::process::id()));
+        std::fs::write(&dir, "").unwrap();
+        let server = TestServer::start(|_, _| Response::ok(list_page(1..=2)));
+        let fetcher = Fetcher::builder()
+            .base_url(&server.base_url)
+            .cache(PageCache::new(&dir, Duration::from_secs(60)))
+            .build()
+            .unwrap();
+        let items = fetcher
+            .get_items("someone", Category::Anime, State::Collect, |_| {})
+            .unwrap();
+        assert_eq!(items.len(), 2);
+        std::fs::remove_file(dir).unwrap();
+    }
+
+    #[test]
+    fn test_get_all_items_concurrently() {
+        // anime/collect has 3 pages, book/collect has 1.
+        let server = TestServer::start(|target, _| {
+            let page: u32 = target.rsplit('=').next().unwrap().parse().unwrap();
+            let body = if target.starts_with("/anime/") {
+                let pager = r#"<div id="multipage"><a href="?page=3">3</a></div>"#;
+                let start = page * 100;
+                pager.to_string() + &list_page(start..start + 24)
+            } else {
+                list_page(1..=5)
+            };
+            // Make earlier pages slower so that they finish last.
+            std::thread::sleep(Duration::from_millis(30 * (3 - page as u64)));
+            Response::ok(body)
+        });
+        let fetcher = Fetcher::builder()
+            .base_url(&server.base_url)
+            .concurrency(4)
+            .build()
+            .unwrap();
+        let lists = [
+            (Category::Anime, State::Collect),
+            (Category::Book, State::Collect),
+        ];
+        let items = fetcher.get_all_items("someone", &lists, |_| {}).unwrap();
+        let expected: Vec<_> = (100..124)
+            .chain(200..224)
+            .chain(300..324)
+            .chain(1..=5)
+            .collect();
+        assert_eq!(items.iter().map(|i| i.id).collect::<Vec<_>>(), expected);
+        assert_eq!(server.requests(), 4);
+    }
+
+    #[test]
+    fn test_get_all_items_error() {
+        let server = TestServer::start(|target, _| {
+            if target.ends_with("page=2") {
+                Response::status(403)
+            } else {
+                let pager = r#"<div id="multipage"><a href="?page=5">5</a></div>"#;
+                Response::ok(pager.to_string() + &list_page(1..=24))
+            }
+        });
+        let fetcher = Fetcher::builder()
+            .base_url(&server.base_url)
+            .concurrency(2)
+            .build()
+            .unwrap();
+        let lists = [(Category::Anime, State::Collect)];
+        let result = fetcher.get_all_items("someone", &lists, |_| {});
+        assert!(matches!(result, Err(Error::Status { status: 403, .. })));
+    }
+
+    #[test]
+    fn test_session_cookie() {
+        let server = TestServer::start(|_, _| Response::ok(list_page(1..=2)));
+        let fetcher = Fetcher::builder()
+            .base_url(&server.base_url)
+            .cookie(SessionCookie::new("chii_auth=secret").unwrap())
+            .build()
+            .unwrap();
+        fetcher
+            .get_items("someone", Category::Anime, State::Collect, |_| {})
+            .unwrap();
+        let heads = server.request_heads();
+        assert!(heads[0].contains("cookie: chii_auth=secret\r\n"));
+    }
+
+    #[test]
+    fn test_api_source() {
+        let server = TestServer::start(|target, _| {
+            if !target.starts_with("/v0/users/someone/collections?") {
+                return Response::status(404);
+            }
+            let offset: u32 = target.rsplit('=').next().unwrap().parse().unwrap();
+            let count = if offset == 0 { 50 } else { 10 };
+            Response::ok(collection_page(offset..offset + count, 60))
+        });
+        let fetcher = Fetcher::builder()
+            .source(Source::Api)
+            .api_base_url(&server.base_url)
+            .build()
+            .unwrap();
+        let items = fetcher
+            .get_items("someone", Category::Book, State::Do, |_| {})
+            .unwrap();
+        assert_eq!(items.len(), 60);
+        assert_eq!(items[0].title, "Item 0");
+        assert_eq!(items[0].rating, None);
+        assert_eq!(items[59].id, 59);
+        assert_eq!(items[59].rating, Some(4));
+        let heads = server.request_heads();
+        assert!(heads[1].starts_with(
+            "GET /v0/users/someone/collections?subject_type=1&type=3&limit=50&offset=50 "
+        ));
+
+        let result = fetcher.get_items("nobody", Category::Book, State::Do, |_| {});
+        assert!(matches!(result, Err(Error::UserNotFound(_))));
+    }
+
+    #[test]
+    fn test_lenient_parsing() {
+        let server = TestServer::start(|_, _| {
+            let bad_item = r#"<li id="item_3"><h3><span>Item 3</span></h3></li>"#;
+            let html = list_page(1..=2);
+            Response::ok(html.replace("</ul>", &(bad_item.to_string() + "</ul>")))
+        });
+        let fetch = |parse_mode| {
+            let fetcher = Fetcher::builder()
+                .base_url(&server.base_url)
+                .parse_mode(parse_mode)
+                .build()
+                .unwrap();
+            let mut skipped = vec![];
+            let result = fetcher.get_items("someone", Category::Anime, State::Collect, |event| {
+                if let Progress::ItemSkipped { error, .. } = event {
+                    skipped.push(error);
+                }
+            });
+            (result, skipped)
+        };
+
+        let (result, _) = fetch(ParseMode::Strict);
+        assert!(matches!(result, Err(Error::Parse(e)) if e.field == Field::Title));
+
+        let (result, skipped) = fetch(ParseMode::Lenient);
+        assert_eq!(result.unwrap().len(), 2);
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].item, Some(3));
+    }
+
+    #[test]
+    fn test_get_subject() {
+        let server = TestServer::start(|target, _| match target {
+            "/subject/42" => Response::ok(concat!(
+                r#"<h1 class="nameSingle"><a href="/subject/42">Item</a></h1>"#,
+                r#"<div class="subject_tag_section"><div class="inner">"#,
+                r#"<a class="l"><span>TV</span> <small>3</small></a></div></div>"#,
+            )),
+            "/subject/43" => Response::ok("<p>呜咕，出错了</p>"),
+            _ => Response::status(404),
+        });
+        let fetcher = Fetcher::builder()
+            .base_url(&server.base_url)
+            .build()
+            .unwrap();
+        let subject = fetcher.get_subject(42).unwrap();
+        assert_eq!(subject.tags[0].name, "TV");
+        assert_eq!(subject.tags[0].count, 3);
+        assert_eq!(subject.votes, 0);
+        for id in [43, 44] {
+            let result = fetcher.get_subject(id);
+            assert!(matches!(result, Err(Error::SubjectNotFound(i)) if i == id));
         }
     }
-    result
+
+    #[test]
+    fn test_get_user_profile() {
+        let server = TestServer::start(|target, _| match target {
+            "/user/someone" => Response::ok(concat!(
+                r#"<div id="headerProfile"><div class="name"><a>Someone</a></div></div>"#,
+                r#"<a href="/anime/list/someone/collect">3 看过</a>"#,
+            )),
+            _ => Response::ok("<p>数据库中没有查询到该用户的信息</p>"),
+        });
+        let fetcher = Fetcher::builder()
+            .base_url(&server.base_url)
+            .build()
+            .unwrap();
+        let profile = fetcher.get_user_profile("someone").unwrap();
+        assert_eq!(profile.nickname, "Someone");
+        assert_eq!(profile.count(Category::Anime, State::Collect), 3);
+        let result = fetcher.get_user_profile("nobody");
+        assert!(matches!(result, Err(Error::UserNotFound(user)) if user == "nobody"));
+    }
+
+    #[test]
+    fn test_get_friends() {
+        let server = TestServer::start(|target, _| match target {
+            "/user/someone/friends" => Response::ok(concat!(
+                r#"<div id="headerProfile"><div class="name"><a>Someone</a></div></div>"#,
+                r#"<ul id="memberUserList"><li><a href="/user/other" class="avatar">Other</a></li></ul>"#,
+            )),
+            _ => Response::ok("<p>数据库中没有查询到该用户的信息</p>"),
+        });
+        let fetcher = Fetcher::builder()
+            .base_url(&server.base_url)
+            .build()
+            .unwrap();
+        let friends = fetcher.get_friends("someone").unwrap();
+        assert_eq!(friends.len(), 1);
+        assert_eq!(friends[0].username, "other");
+        assert_eq!(friends[0].nickname, "Other");
+        let result = fetcher.get_friends("nobody");
+        assert!(matches!(result, Err(Error::UserNotFound(user)) if user == "nobody"));
+    }
+
+    #[test]
+    fn test_page_cache_with_cookie() {
+        let dir = std::env::temp_dir().join(format!("bgmrank-auth-{}", std::process::id()));
+        let server = TestServer::start(|_, _| Response::ok(list_page(1..=2)));
+        let make_fetcher = |cookie: Option<&str>| {
+            let mut builder = Fetcher::builder()
+                .base_url(&server.base_url)
+                .cache(PageCache::new(&dir, Duration::from_secs(60)));
+            if let Some(cookie) = cookie {
+                builder = builder.cookie(SessionCookie::new(cookie).unwrap());
+            }
+            builder.build().unwrap()
+        };
+        let fetch = |fetcher: &Fetcher| {
+            fetcher
+                .get_items("someone", Category::Anime, State::Collect, |_| {})
+                .unwrap();
+        };
+
+        // Public pages cached without a cookie are not served with one.
+        fetch(&make_fetcher(None));
+        fetch(&make_fetcher(Some("chii_auth=secret")));
+        assert_eq!(server.requests(), 2);
+        // Nor are pages cached with a cookie served without one.
+        std::fs::remove_dir_all(dir.join("someone")).unwrap();
+        fetch(&make_fetcher(None));
+        assert_eq!(server.requests(), 3);
+        // Each is still served from the cache to later runs of its kind.
+        fetch(&make_fetcher(Some("chii_auth=secret")));
+        fetch(&make_fetcher(None));
+        assert_eq!(server.requests(), 3);
+        let heads = server.request_heads();
+        assert!(!heads[2].contains("cookie:"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
@@ -0,0 +1,315 @@
+use crate::cache::PageKey;
+use crate::data::{Category, Friend, Id, Item, State, Subject, UserProfile};
+use crate::error::Error;
+use crate::fetch::{self, FetchConfig, FetcherBuilder, PageCount, ParsedPage};
+use crate::progress::Progress;
+use futures_util::stream::{self, Stream};
+use reqwest::header::COOKIE;
+use reqwest::{Client, Proxy};
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::{self, Instant};
+
+impl FetcherBuilder {
+    pub fn build_async(mut self) -> Result<AsyncFetcher, Error> {
+        let client = match self.async_client.take() {
+            Some(client) => client,
+            None => {
+                let mut builder = Client::builder().user_agent(&self.user_agent);
+                if let Some(timeout) = self.timeout {
+                    builder = builder.timeout(timeout);
+                }
+                if let Some(proxy) = &self.proxy {
+                    builder = builder.proxy(Proxy::all(proxy)?);
+                }
+                builder.build()?
+            }
+        };
+        Ok(AsyncFetcher {
+            client,
+            last_request: Mutex::new(None),
+            config: self.into_config(),
+        })
+    }
+}
+
+/// The async counterpart of `Fetcher`, sharing the same configuration
+/// and parser. It requires a tokio runtime.
+pub struct AsyncFetcher {
+    client: Client,
+    last_request: Mutex<Option<Instant>>,
+    config: FetchConfig,
+}
+
+impl AsyncFetcher {
+    pub fn new() -> Result<Self, Error> {
+        Self::builder().build_async()
+    }
+
+    pub fn builder() -> FetcherBuilder {
+        FetcherBuilder::default()
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.config.base_url
+    }
+
+    /// Returns the URL of the subject on the configured site.
+    pub fn subject_url(&self, id: Id) -> String {
+        self.config.subject_url(id)
+    }
+
+    async fn wait_for_turn(&self) {
+        let mut last_request = self.last_request.lock().await;
+        if let Some(last) = *last_request {
+            time::sleep_until(last + self.config.min_interval).await;
+        }
+        *last_request = Some(Instant::now());
+    }
+
+    /// Fetches `url`, and returns its content, or `None` if the server
+    /// responds with 404. Calls `on_retry` with the next attempt and the
+    /// delay before it when retrying.
+    async fn fetch_url(
+        &self,
+        url: &str,
+        mut on_retry: impl FnMut(u32, Duration),
+    ) -> Result<Option<String>, Error> {
+        let mut attempt = 1;
+        let resp = loop {
+            self.wait_for_turn().await;
+            let mut request = self.client.get(url);
+            if let Some(cookie) = &self.config.cookie {
+                request = request.header(COOKIE, cookie.header_value());
+            }
+            let result = request.send().await;
+            let response = result.as_ref().map(|resp| (resp.status(), resp.headers()));
+            let delay = match self.config.retry_delay(attempt, response) {
+                Some(delay) => delay,
+                None => break result?,
+            };
+            attempt += 1;
+            on_retry(attempt, delay);
+            time::sleep(delay).await;
+        };
+        if !fetch::check_status(url, resp.status())? {
+            return Ok(None);
+        }
+        Ok(Some(resp.text().await?))
+    }
+
+    /// Fetches the page of the subject and parses the community tags and
+    /// ratings on it.
+    pub async fn get_subject(&self, id: Id) -> Result<Subject, Error> {
+        let html = self.fetch_url(&self.subject_url(id), |_, _| {}).await?;
+        fetch::parse_subject_page(id, html.as_deref())
+    }
+
+    /// Fetches the profile page of the user, which includes the number
+    /// of items in each list.
+    pub async fn get_user_profile(&self, username: &str) -> Result<UserProfile, Error> {
+        let url = self.config.profile_url(username);
+        let html = self.fetch_url(&url, |_, _| {}).await?;
+        fetch::parse_profile_page(username, html.as_deref())
+    }
+
+    /// Fetches the friend list of the user.
+    pub async fn get_friends(&self, username: &str) -> Result<Vec<Friend>, Error> {
+        let url = self.config.friends_url(username);
+        let html = self.fetch_url(&url, |_, _| {}).await?;
+        fetch::parse_friends_page(username, html.as_deref())
+    }
+
+    async fn get_page_items(
+        &self,
+        key: &PageKey<'_>,
+        notify: &mut impl FnMut(Progress),
+    ) -> Result<ParsedPage, Error> {
+        notify(Progress::PageStarted {
+            category: key.category,
+            state: key.state,
+            page: key.page,
+        });
+        let page = match self.config.cached_page(key)? {
+            Some(page) => page,
+            None => {
+                let url = self.config.list_url(key);
+                let body = self
+                    .fetch_url(&url, |attempt, delay| {
+                        notify(Progress::Retrying {
+                            category: key.category,
+                            state: key.state,
+                            page: key.page,
+                            attempt,
+                            delay,
+                        })
+                    })
+                    .await?;
+                self.config.parse_page(key, body.as_deref())?
+            }
+        };
+        Ok(fetch::report_page(key, page, notify))
+    }
+
+    /// Fetches all items of the list, reporting progress to the observer.
+    pub async fn get_items(
+        &self,
+        username: &str,
+        category: Category,
+        state: State,
+        mut observer: impl FnMut(Progress),
+    ) -> Result<Vec<Item>, Error> {
+        let mut result = vec![];
+        let mut page_count = PageCount::default();
+        let mut next = Some(1);
+        while let Some(page) = next {
+            let key = PageKey {
+                username,
+                category,
+                state,
+                page,
+            };
+            let parsed = self.get_page_items(&key, &mut observer).await?;
+            next = page_count.next_page(&key, &parsed, &mut observer);
+            result.extend(parsed.items);
+        }
+        observer(Progress::Finished {
+            items: result.len(),
+        });
+        Ok(result)
+    }
+
+    /// Returns a stream yielding the items of each page of the list in
+    /// order. The stream ends after the last page or the first error.
+    pub fn pages<'a>(
+        &'a self,
+        username: &'a str,
+        category: Category,
+        state: State,
+    ) -> impl Stream<Item = Result<Vec<Item>, Error>> + 'a {
+        let start = Some((1, PageCount::default()));
+        stream::unfold(start, move |next| async move {
+            let (page, mut page_count) = next?;
+            let key = PageKey {
+                username,
+                category,
+                state,
+                page,
+            };
+            match self.get_page_items(&key, &mut |_| {}).await {
+                Ok(parsed) => {
+                    let next = page_count
+                        .next_page(&key, &parsed, |_| {})
+                        .map(|page| (page, page_count));
+                    Some((Ok(parsed.items), next))
+                }
+                Err(e) => Some((Err(e), None)),
+            }
+        })
+    }
+}
+
+/// Fetches items from bgm.tv with the default settings.
+pub async fn get_items_async(
+    username: &str,
+    category: Category,
+    state: State,
+    observer: impl FnMut(Progress),
+) -> Result<Vec<Item>, Error> {
+    AsyncFetcher::new()?
+        .get_items(username, category, state, observer)
+        .await
+}
+
+#[cfg(test)]
+mod test {
+    use super::AsyncFetcher;
+    use crate::auth::SessionCookie;
+    use crate::data::{Category, State};
+    use crate::error::Error;
+    use crate::retry::RetryPolicy;
+    use crate::test_server::{list_page, Response, TestServer};
+    use futures_util::TryStreamExt;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn start_server() -> TestServer {
+        TestServer::start(|target, index| {
+            if index == 0 {
+                return Response::status(503);
+            }
+            if !target.starts_with("/anime/list/someone/") {
+                return Response::status(404);
+            }
+            let page: u32 = target.rsplit('=').next().unwrap().parse().unwrap();
+            let pager = r#"<div id="multipage"><a href="?page=2">2</a></div>"#;
+            let count = if page == 1 { 24 } else { 3 };
+            Response::ok(pager.to_string() + &list_page(page * 100..page * 100 + count))
+        })
+    }
+
+    fn build_fetcher(server: &TestServer) -> AsyncFetcher {
+        AsyncFetcher::builder()
+            .base_url(&server.base_url)
+            .retry_policy(RetryPolicy {
+                initial_backoff: Duration::from_millis(1),
+                ..Default::default()
+            })
+            .build_async()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_get_items_async() {
+        let server = start_server();
+        let fetcher = Arc::new(build_fetcher(&server));
+        // Make sure the future can be spawned onto a multi-threaded runtime.
+        let items = tokio::spawn({
+            let fetcher = fetcher.clone();
+            async move {
+                fetcher
+                    .get_items("someone", Category::Anime, State::Collect, |_| {})
+                    .await
+            }
+        })
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(items.len(), 27);
+        assert_eq!(server.requests(), 3);
+
+        let result = fetcher
+            .get_items("nobody", Category::Anime, State::Collect, |_| {})
+            .await;
+        assert!(matches!(result, Err(Error::UserNotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_pages_stream() {
+        let server = start_server();
+        let fetcher = build_fetcher(&server);
+        let pages: Vec<_> = fetcher
+            .pages("someone", Category::Anime, State::Collect)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].len(), 24);
+        assert_eq!(pages[1][0].id, 200);
+    }
+
+    #[tokio::test]
+    async fn test_session_cookie() {
+        let server = TestServer::start(|_, _| Response::ok(list_page(1..=2)));
+        let fetcher = AsyncFetcher::builder()
+            .base_url(&server.base_url)
+            .cookie(SessionCookie::new("chii_auth=secret").unwrap())
+            .build_async()
+            .unwrap();
+        fetcher
+            .get_items("someone", Category::Anime, State::Collect, |_| {})
+            .await
+            .unwrap();
+        assert!(server.request_heads()[0].contains("cookie: chii_auth=secret\r\n"));
+    }
+}
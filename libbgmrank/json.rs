@@ -0,0 +1,282 @@
+use crate::data::{Category, Id, Item, Rating, State};
+use crate::error::Error;
+use crate::source::CollectionSource;
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+use strum::IntoEnumIterator;
+
+/// A page of collections as returned by the Bangumi v0 API
+/// (`/v0/users/{username}/collections`).
+#[derive(Deserialize)]
+struct CollectionPage {
+    total: usize,
+    data: Vec<UserCollection>,
+}
+
+#[derive(Clone, Deserialize)]
+struct UserCollection {
+    subject_id: Id,
+    subject_type: u8,
+    #[serde(rename = "type")]
+    collection_type: u8,
+    /// The rating, where 0 means not rated.
+    rate: Rating,
+    #[serde(default)]
+    tags: Vec<String>,
+    subject: Option<SlimSubject>,
+    comment: Option<String>,
+    /// When the collection was last updated, as an RFC 3339 timestamp.
+    updated_at: Option<String>,
+}
+
+#[derive(Clone, Default, Deserialize)]
+struct SlimSubject {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    name_cn: String,
+    /// The release date in the form of `2019-04-06`.
+    date: Option<String>,
+    #[serde(default)]
+    eps: u32,
+    #[serde(default)]
+    volumes: u32,
+    #[serde(default)]
+    score: f32,
+    images: Option<Images>,
+    /// The rank on the site, where 0 means not ranked.
+    #[serde(default)]
+    rank: u32,
+}
+
+/// URLs of the cover image in different sizes.
+#[derive(Clone, Deserialize)]
+struct Images {
+    /// The size used for the thumbnails on list pages.
+    common: String,
+}
+
+impl From<UserCollection> for Item {
+    fn from(collection: UserCollection) -> Self {
+        let subject = collection.subject.unwrap_or_default();
+        let (name, name_cn) = (subject.name, subject.name_cn);
+        let non_empty = |name: &String| Some(name.clone()).filter(|name| !name.is_empty());
+        let (title_original, title_cn) = (non_empty(&name), non_empty(&name_cn));
+        // Books count volumes rather than episodes.
+        let episodes = Some(subject.eps)
+            .filter(|&eps| eps > 0)
+            .or(Some(subject.volumes).filter(|&volumes| volumes > 0));
+        Item {
+            id: collection.subject_id,
+            // List pages show the original name when there is one, so
+            // prefer it here as well to get the same titles from both
+            // sources.
+            title: if name.is_empty() { name_cn } else { name },
+            title_cn,
+            title_original,
+            rating: Some(collection.rate).filter(|&rate| rate > 0),
+            tags: collection.tags,
+            collected_at: collection
+                .updated_at
+                .and_then(|time| time.get(..10)?.parse().ok()),
+            comment: collection.comment.filter(|comment| !comment.is_empty()),
+            // The API has no equivalent of the metadata line.
+            info: None,
+            release_date: subject.date.and_then(|date| date.parse().ok()),
+            episodes,
+            cover_url: subject
+                .images
+                .map(|images| images.common)
+                .filter(|url| !url.is_empty()),
+            site_rank: Some(subject.rank).filter(|&rank| rank > 0),
+            site_score: Some(subject.score).filter(|&score| score > 0.0),
+            category: Category::iter()
+                .find(|&category| subject_type(category) == collection.subject_type),
+            state: State::iter()
+                .find(|&state| collection_type(state) == collection.collection_type),
+        }
+    }
+}
+
+/// Parses the items from a page of collections returned by the v0 API.
+pub fn parse_collections(json: &str) -> Result<Vec<Item>, Error> {
+    Ok(parse_collection_page(json)?.0)
+}
+
+/// Parses a page of collections, returning its items along with the
+/// total number of collections in the list.
+pub(crate) fn parse_collection_page(json: &str) -> Result<(Vec<Item>, usize), Error> {
+    let page: CollectionPage = serde_json::from_str(json)?;
+    Ok((page.data.into_iter().map(Item::from).collect(), page.total))
+}
+
+/// Returns the `subject_type` the API uses for the category.
+pub(crate) fn subject_type(category: Category) -> u8 {
+    match category {
+        Category::Book => 1,
+        Category::Anime => 2,
+        Category::Music => 3,
+        Category::Game => 4,
+        Category::Real => 6,
+    }
+}
+
+/// Returns the collection `type` the API uses for the state.
+pub(crate) fn collection_type(state: State) -> u8 {
+    match state {
+        State::Wish => 1,
+        State::Collect => 2,
+        State::Do => 3,
+        State::OnHold => 4,
+        State::Dropped => 5,
+    }
+}
+
+/// A collection export in the format of the v0 API, i.e. either a
+/// single page as returned by `/v0/users/{username}/collections`, or an
+/// array of the collections in such pages.
+///
+/// An export holds the collections of a single user, so the username
+/// is ignored when getting items from it.
+pub struct JsonExport {
+    collections: Vec<UserCollection>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ExportFormat {
+    Page(CollectionPage),
+    List(Vec<UserCollection>),
+}
+
+impl JsonExport {
+    pub fn parse(json: &str) -> Result<Self, Error> {
+        let collections = match serde_json::from_str(json)? {
+            ExportFormat::Page(page) => page.data,
+            ExportFormat::List(collections) => collections,
+        };
+        Ok(JsonExport { collections })
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+}
+
+impl CollectionSource for JsonExport {
+    fn get_items(
+        &self,
+        _username: &str,
+        category: Category,
+        state: State,
+    ) -> Result<Vec<Item>, Error> {
+        let (subject_type, collection_type) = (subject_type(category), collection_type(state));
+        Ok(self
+            .collections
+            .iter()
+            .filter(|c| c.subject_type == subject_type && c.collection_type == collection_type)
+            .cloned()
+            .map(Item::from)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_collection_page, JsonExport};
+    use crate::data::{Category, State};
+    use crate::source::CollectionSource;
+
+    #[test]
+    fn test_parse_collections() {
+        let json = r#"{
+            "data": [
+                {
+                    "updated_at": "2023-04-01T20:13:42+08:00",
+                    "comment": null,
+                    "tags": ["TV", "京都动画"],
+                    "subject": {"id": 1424, "type": 2, "name": "けいおん！", "name_cn": "轻音少女", "date": "2009-04-02", "eps": 13, "score": 8.1, "rank": 180, "images": {"common": "https://lain.bgm.tv/pic/cover/c/1424.jpg", "small": ""}},
+                    "subject_id": 1424,
+                    "subject_type": 2,
+                    "type": 2,
+                    "rate": 8,
+                    "private": false
+                },
+                {
+                    "tags": [],
+                    "comment": "一般",
+                    "subject": {"id": 2, "type": 2, "name": "", "name_cn": "中文名"},
+                    "subject_id": 2,
+                    "subject_type": 1,
+                    "type": 3,
+                    "rate": 0
+                }
+            ],
+            "total": 2,
+            "limit": 30,
+            "offset": 0
+        }"#;
+        let (items, total) = parse_collection_page(json).unwrap();
+        assert_eq!(total, 2);
+        assert_eq!(items[0].id, 1424);
+        assert_eq!(items[0].title, "けいおん！");
+        assert_eq!(items[0].title_cn.as_deref(), Some("轻音少女"));
+        assert_eq!(items[0].title_original.as_deref(), Some("けいおん！"));
+        assert_eq!(items[0].rating, Some(8));
+        assert_eq!(items[0].tags, ["TV", "京都动画"]);
+        assert_eq!(items[0].collected_at.unwrap().to_string(), "2023-04-01");
+        assert_eq!(items[0].comment, None);
+        assert_eq!(items[0].release_date.unwrap().to_string(), "2009-04-02");
+        assert_eq!(items[0].episodes, Some(13));
+        assert_eq!(items[1].episodes, None);
+        assert_eq!(
+            items[0].cover_url.as_deref(),
+            Some("https://lain.bgm.tv/pic/cover/c/1424.jpg")
+        );
+        assert_eq!(items[1].cover_url, None);
+        assert_eq!(items[0].site_rank, Some(180));
+        assert_eq!(items[0].site_score, Some(8.1));
+        assert_eq!(items[1].site_score, None);
+        assert_eq!(items[1].comment.as_deref(), Some("一般"));
+        assert_eq!(items[1].title, "中文名");
+        assert_eq!(items[1].title_original, None);
+        assert_eq!(items[1].rating, None);
+        assert_eq!(items[0].category, Some(Category::Anime));
+        assert_eq!(items[0].state, Some(State::Collect));
+        assert_eq!(items[1].category, Some(Category::Book));
+        assert_eq!(items[1].state, Some(State::Do));
+    }
+
+    #[test]
+    fn test_json_export() {
+        let collection = |id, subject_type, collection_type| {
+            format!(
+                r#"{{"subject_id":{},"subject_type":{},"type":{},"rate":0,"subject":null}}"#,
+                id, subject_type, collection_type
+            )
+        };
+        let json = format!(
+            "[{},{},{}]",
+            collection(1, 2, 2),
+            collection(2, 1, 2),
+            collection(3, 2, 2)
+        );
+        let export = JsonExport::parse(&json).unwrap();
+        let items = export
+            .get_items("anyone", Category::Anime, State::Collect)
+            .unwrap();
+        assert_eq!(items.iter().map(|i| i.id).collect::<Vec<_>>(), [1, 3]);
+        let items = export
+            .get_items("anyone", Category::Anime, State::Wish)
+            .unwrap();
+        assert!(items.is_empty());
+
+        let page = format!(r#"{{"data":[{}],"total":1}}"#, collection(2, 1, 2));
+        let export = JsonExport::parse(&page).unwrap();
+        let items = export
+            .get_items("", Category::Book, State::Collect)
+            .unwrap();
+        assert_eq!(items[0].id, 2);
+    }
+}
@@ -1,10 +1,53 @@
+#[cfg(any(feature = "blocking", feature = "async"))]
+mod auth;
+#[cfg(any(feature = "blocking", feature = "async"))]
+mod cache;
+#[cfg(feature = "stats")]
 mod classifier;
 mod data;
+mod error;
+#[cfg(any(feature = "blocking", feature = "async"))]
 mod fetch;
+#[cfg(feature = "async")]
+mod fetch_async;
+#[cfg(feature = "parser")]
 mod helpers;
+#[cfg(feature = "json")]
+mod json;
+#[cfg(feature = "parser")]
+mod offline;
+#[cfg(feature = "parser")]
 mod parser;
+#[cfg(any(feature = "blocking", feature = "async"))]
+mod progress;
+#[cfg(any(feature = "blocking", feature = "async"))]
+mod retry;
+mod source;
+#[cfg(feature = "stats")]
 mod stats;
+#[cfg(all(test, any(feature = "blocking", feature = "async")))]
+mod test_server;
 
+#[cfg(any(feature = "blocking", feature = "async"))]
+pub use crate::auth::{SessionCookie, COOKIE_ENV_VAR};
+#[cfg(any(feature = "blocking", feature = "async"))]
+pub use crate::cache::{PageCache, DEFAULT_CACHE_TTL};
 pub use crate::data::*;
+pub use crate::error::*;
+#[cfg(any(feature = "blocking", feature = "async"))]
 pub use crate::fetch::*;
+#[cfg(feature = "async")]
+pub use crate::fetch_async::*;
+#[cfg(feature = "json")]
+pub use crate::json::{parse_collections, JsonExport};
+#[cfg(feature = "parser")]
+pub use crate::offline::*;
+#[cfg(feature = "parser")]
+pub use crate::parser::ParseMode;
+#[cfg(any(feature = "blocking", feature = "async"))]
+pub use crate::progress::Progress;
+#[cfg(any(feature = "blocking", feature = "async"))]
+pub use crate::retry::RetryPolicy;
+pub use crate::source::*;
+#[cfg(feature = "stats")]
 pub use crate::stats::*;
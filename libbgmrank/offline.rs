@@ -0,0 +1,207 @@
+use crate::data::{Category, Friend, Item, State, Subject, UserProfile};
+use crate::error::{Error, ParseError};
+use crate::parser::{self, ParseMode};
+use crate::source::{set_list, CollectionSource};
+use html5ever::tendril::TendrilSink;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Parses the items from the HTML of a saved list page.
+pub fn parse_items(html: &str) -> Result<Vec<Item>, Error> {
+    let doc = kuchiki::parse_html().one(html);
+    Ok(parser::get_all_items(doc, ParseMode::Strict)?.0)
+}
+
+/// Parses the items from the HTML of a saved list page, skipping items
+/// which fail to parse. Returns the items along with the errors of the
+/// skipped ones.
+pub fn parse_items_lenient(html: &str) -> (Vec<Item>, Vec<ParseError>) {
+    let doc = kuchiki::parse_html().one(html);
+    parser::get_all_items(doc, ParseMode::Lenient).unwrap()
+}
+
+/// Parses the community tags and ratings from the HTML of a saved
+/// subject page.
+pub fn parse_subject(html: &str) -> Result<Subject, Error> {
+    let doc = kuchiki::parse_html().one(html);
+    Ok(parser::get_subject(&doc)?)
+}
+
+/// Parses the profile of the user from the HTML of a saved profile
+/// page.
+pub fn parse_user_profile(html: &str, username: &str) -> Result<UserProfile, Error> {
+    let doc = kuchiki::parse_html().one(html);
+    Ok(parser::get_user_profile(&doc, username)?)
+}
+
+/// Parses the friend list from the HTML of a saved friends page.
+pub fn parse_friends(html: &str) -> Vec<Friend> {
+    let doc = kuchiki::parse_html().one(html);
+    parser::get_friends(&doc)
+}
+
+pub fn read_items_from_file(path: impl AsRef<Path>) -> Result<Vec<Item>, Error> {
+    parse_items(&fs::read_to_string(path)?)
+}
+
+pub fn read_items_from_files<P: AsRef<Path>>(
+    paths: impl IntoIterator<Item = P>,
+) -> Result<Vec<Item>, Error> {
+    let mut result = vec![];
+    for path in paths {
+        result.extend(read_items_from_file(path)?);
+    }
+    Ok(result)
+}
+
+/// Reads the items from all `.html` files in the given directory.
+///
+/// Files are read in the order of the last number in their names, so
+/// pages saved as e.g. `collect_page_2.html` and `collect_page_10.html`
+/// keep the order they have on the site.
+pub fn read_items_from_dir(dir: impl AsRef<Path>) -> Result<Vec<Item>, Error> {
+    read_items_from_files(list_pages(dir.as_ref())?)
+}
+
+/// Lists the `.html` files in the directory in the order of their pages.
+fn list_pages(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut paths = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|ext| ext == "html") {
+            paths.push(path);
+        }
+    }
+    paths.sort_by_cached_key(|path| (get_page_number(path), path.clone()));
+    Ok(paths)
+}
+
+fn get_page_number(path: &Path) -> Option<u32> {
+    let stem = path.file_stem()?.to_str()?;
+    let end = stem.rfind(|c: char| c.is_ascii_digit())? + 1;
+    let start = stem[..end]
+        .rfind(|c: char| !c.is_ascii_digit())
+        .map_or(0, |i| i + 1);
+    stem[start..end].parse().ok()
+}
+
+type SkipHandler = Box<dyn Fn(&ParseError)>;
+
+/// A source reading list pages saved under `<dir>/<category>/<state>/`.
+///
+/// The directory holds the pages of a single user, so the username is
+/// ignored when getting items from it.
+pub struct HtmlDirSource {
+    dir: PathBuf,
+    on_skip: Option<SkipHandler>,
+}
+
+impl HtmlDirSource {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        HtmlDirSource {
+            dir: dir.into(),
+            on_skip: None,
+        }
+    }
+
+    /// Skips items which fail to parse instead of failing, and calls
+    /// `on_skip` with the error of each skipped item.
+    pub fn lenient(mut self, on_skip: impl Fn(&ParseError) + 'static) -> Self {
+        self.on_skip = Some(Box::new(on_skip));
+        self
+    }
+}
+
+impl CollectionSource for HtmlDirSource {
+    fn get_items(
+        &self,
+        _username: &str,
+        category: Category,
+        state: State,
+    ) -> Result<Vec<Item>, Error> {
+        // Fail if the directory itself is missing, e.g. from a typo, rather
+        // than treating every list in it as empty.
+        fs::metadata(&self.dir)?;
+        let dir = self.dir.join(category.to_string()).join(state.to_string());
+        let pages = match list_pages(&dir) {
+            Ok(pages) => pages,
+            // Lists which were never saved are empty, as in `MemorySource`.
+            Err(Error::Io(e)) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+        let mut result = match &self.on_skip {
+            None => read_items_from_files(pages)?,
+            Some(on_skip) => {
+                let mut result = vec![];
+                for path in pages {
+                    let (items, skipped) = parse_items_lenient(&fs::read_to_string(path)?);
+                    result.extend(items);
+                    skipped.iter().for_each(on_skip);
+                }
+                result
+            }
+        };
+        set_list(&mut result, category, state);
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{read_items_from_dir, HtmlDirSource};
+    use crate::data::{Category, State};
+    use crate::error::Error;
+    use crate::source::CollectionSource;
+    use std::fs;
+    use std::io;
+
+    fn list_page(id: u32) -> String {
+        format!(
+            r#"<ul id="browserItemList"><li id="item_{0}"><h3><a>{0}</a></h3></li></ul>"#,
+            id
+        )
+    }
+
+    #[test]
+    fn test_read_items_from_dir() {
+        let dir = std::env::temp_dir().join(format!("bgmrank-offline-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("page=10.html"), list_page(3)).unwrap();
+        fs::write(dir.join("page=2.html"), list_page(2)).unwrap();
+        fs::write(dir.join("page=1.html"), list_page(1)).unwrap();
+        fs::write(dir.join("notes.txt"), "not a page").unwrap();
+        let items = read_items_from_dir(&dir).unwrap();
+        assert_eq!(items.iter().map(|i| i.id).collect::<Vec<_>>(), [1, 2, 3]);
+        // The pages don't tell which list they belong to.
+        assert_eq!(items[0].category, None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_html_dir_source() {
+        let dir = std::env::temp_dir().join(format!("bgmrank-html-dir-{}", std::process::id()));
+        let list_dir = dir.join("anime").join("collect");
+        fs::create_dir_all(&list_dir).unwrap();
+        fs::write(list_dir.join("1.html"), list_page(1)).unwrap();
+        let source = HtmlDirSource::new(&dir);
+        let lists = [
+            (Category::Anime, State::Wish),
+            (Category::Anime, State::Collect),
+        ];
+        let items = source.get_all_items("", &lists).unwrap();
+        assert_eq!(items.iter().map(|i| i.id).collect::<Vec<_>>(), [1]);
+        assert_eq!(items[0].state, Some(State::Collect));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_html_dir_source_missing_dir() {
+        let dir = std::env::temp_dir().join(format!("bgmrank-no-dir-{}", std::process::id()));
+        let source = HtmlDirSource::new(&dir);
+        match source.get_items("", Category::Anime, State::Collect) {
+            Err(Error::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::NotFound),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+}
@@ -1,78 +1,680 @@
-use crate::data::{Id, Item, Rating};
+use crate::data::{
+    Category, Date, Friend, Id, Item, Rating, State, Subject, TagCount, UserProfile, MAX_RATING,
+};
+use crate::error::{Field, ParseError};
 use crate::helpers::{ElementDataRef, QuerySelector};
 use html5ever::{expanded_name, local_name, namespace_url, ns};
 use kuchiki::NodeRef;
+use std::collections::HashMap;
 
-fn get_item_id(elem: &ElementDataRef) -> Id {
+/// Maximum length in characters of the snippets in parse errors.
+const MAX_SNIPPET_LEN: usize = 200;
+
+/// How to deal with items which fail to parse.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Fail on the first item that can't be parsed.
+    #[default]
+    Strict,
+    /// Skip items that can't be parsed, and return their errors along
+    /// with the other items.
+    Lenient,
+}
+
+fn make_error(
+    field: Field,
+    item: Option<Id>,
+    selector: &'static str,
+    node: &NodeRef,
+) -> ParseError {
+    let mut snippet = node.to_string();
+    if let Some((end, _)) = snippet.char_indices().nth(MAX_SNIPPET_LEN) {
+        snippet.truncate(end);
+        snippet.push('…');
+    }
+    ParseError {
+        field,
+        item,
+        selector,
+        snippet,
+    }
+}
+
+fn get_item_id(elem: &ElementDataRef) -> Result<Id, ParseError> {
     static ID_PREFIX: &str = "item_";
+    let error = || make_error(Field::Id, None, "li[id]", elem.as_node());
     let attrs = elem.attributes.borrow();
-    let id = attrs.get(local_name!("id")).unwrap();
-    let (prefix, id_str) = id.split_at(ID_PREFIX.len());
-    assert!(prefix == ID_PREFIX);
-    id_str.parse().unwrap()
+    let id = attrs.get(local_name!("id")).ok_or_else(error)?;
+    let id_str = id.strip_prefix(ID_PREFIX).ok_or_else(error)?;
+    id_str.parse().map_err(|_| error())
+}
+
+fn get_item_title(elem: &ElementDataRef, id: Id) -> Result<String, ParseError> {
+    static SELECTOR: &str = "h3>*:last-child";
+    let title_node = elem
+        .query_selector(SELECTOR)
+        .ok_or_else(|| make_error(Field::Title, Some(id), SELECTOR, elem.as_node()))?;
+    if title_node.name.expanded() != expanded_name!(html "small")
+        && title_node.name.expanded() != expanded_name!(html "a")
+    {
+        let h3 = title_node.as_node().parent().unwrap();
+        return Err(make_error(Field::Title, Some(id), SELECTOR, &h3));
+    }
+    Ok(title_node.text_contents())
 }
 
-fn get_item_title(elem: &ElementDataRef) -> String {
-    let title_node = elem.query_selector("h3>*:last-child").unwrap();
-    assert!(
-        title_node.name.expanded() == expanded_name!(html "small")
-            || title_node.name.expanded() == expanded_name!(html "a")
-    );
-    title_node.text_contents()
+/// Returns the localized and the original titles. List pages show the
+/// localized title in the link with the original one after it, or only
+/// the original title in the link if there is no localized one.
+fn get_item_titles(elem: &ElementDataRef) -> (Option<String>, Option<String>) {
+    let link = elem.query_selector("h3>a").map(|a| a.text_contents());
+    let small = elem.query_selector("h3>small").map(|s| s.text_contents());
+    let (title_cn, title_original) = match (link, small) {
+        (Some(link), Some(small)) => (Some(link), Some(small)),
+        (link, small) => (None, link.or(small)),
+    };
+    let trim = |title: String| Some(title.trim().to_string()).filter(|t| !t.is_empty());
+    (title_cn.and_then(trim), title_original.and_then(trim))
 }
 
-fn get_item_rating(elem: &ElementDataRef) -> Option<Rating> {
+fn get_item_rating(elem: &ElementDataRef, id: Id) -> Result<Option<Rating>, ParseError> {
     static STARS_PREFIX: &str = "stars";
-    let elem = elem.query_selector(".starlight")?;
+    static SELECTOR: &str = ".starlight";
+    let elem = match elem.query_selector(SELECTOR) {
+        Some(elem) => elem,
+        None => return Ok(None),
+    };
     let attrs = elem.attributes.borrow();
-    let classes = attrs.get(local_name!("class")).unwrap();
-    let result = classes
-        .split_whitespace()
-        .find_map(|class| {
-            if !class.starts_with(STARS_PREFIX) {
-                return None;
-            }
-            let rating = class[STARS_PREFIX.len()..].parse().unwrap();
-            assert!((1..=10).contains(&rating));
-            Some(rating)
+    let rating = attrs
+        .get(local_name!("class"))
+        .and_then(|classes| {
+            classes
+                .split_whitespace()
+                .find_map(|class| class.strip_prefix(STARS_PREFIX))
         })
-        .unwrap();
-    Some(result)
+        .and_then(|rating| rating.parse().ok())
+        .filter(|rating| (1..=MAX_RATING).contains(rating))
+        .ok_or_else(|| make_error(Field::Rating, Some(id), SELECTOR, elem.as_node()))?;
+    Ok(Some(rating))
 }
 
-fn get_item_tags(elem: &ElementDataRef) -> Vec<String> {
+fn get_item_tags(elem: &ElementDataRef, id: Id) -> Result<Vec<String>, ParseError> {
     static TAGS_PREFIX: &str = "标签: ";
-    if let Some(tags_elem) = elem.query_selector(".collectInfo>.tip") {
-        let all_text = tags_elem.text_contents();
-        let tag_text = all_text.trim();
-        assert!(tag_text.starts_with(TAGS_PREFIX));
-        tag_text[TAGS_PREFIX.len()..]
-            .split(' ')
-            .filter_map(|s| {
-                if !s.is_empty() {
-                    Some(s.to_string())
-                } else {
-                    None
-                }
-            })
-            .collect()
-    } else {
-        vec![]
+    static SELECTOR: &str = ".collectInfo>.tip";
+    let tags_elem = match elem.query_selector(SELECTOR) {
+        Some(tags_elem) => tags_elem,
+        None => return Ok(vec![]),
+    };
+    let all_text = tags_elem.text_contents();
+    let tag_text = all_text
+        .trim()
+        .strip_prefix(TAGS_PREFIX)
+        .ok_or_else(|| make_error(Field::Tags, Some(id), SELECTOR, tags_elem.as_node()))?;
+    Ok(tag_text
+        .split(' ')
+        .filter_map(|s| {
+            if !s.is_empty() {
+                Some(s.to_string())
+            } else {
+                None
+            }
+        })
+        .collect())
+}
+
+fn get_item_collected_at(elem: &ElementDataRef, id: Id) -> Result<Option<Date>, ParseError> {
+    static SELECTOR: &str = ".collectInfo .tip_j";
+    let date_elem = match elem.query_selector(SELECTOR) {
+        Some(date_elem) => date_elem,
+        None => return Ok(None),
+    };
+    let date = date_elem
+        .text_contents()
+        .parse()
+        .map_err(|_| make_error(Field::CollectedAt, Some(id), SELECTOR, date_elem.as_node()))?;
+    Ok(Some(date))
+}
+
+fn get_item_comment(elem: &ElementDataRef) -> Option<String> {
+    let text = elem.query_selector("#comment_box .text")?.text_contents();
+    Some(text.trim().to_string()).filter(|text| !text.is_empty())
+}
+
+/// Returns the raw metadata line along with the release date and the
+/// number of episodes found in it. The line has no fixed format, so
+/// fields which can't be recognized are left empty.
+fn get_item_info(elem: &ElementDataRef) -> (Option<String>, Option<Date>, Option<u32>) {
+    static EPISODE_SUFFIXES: &[char] = &['话', '話', '集', '卷', '册'];
+    let info = elem
+        .query_selector(".info.tip")
+        .map(|info| info.text_contents().trim().to_string())
+        .filter(|info| !info.is_empty());
+    let fields = || info.iter().flat_map(|info| info.split('/')).map(str::trim);
+    let release_date = fields().find_map(|field| {
+        // Dates are shown either as 2019-04-06 or as 2019年4月6日.
+        let field = field.replace(['年', '月'], "-").replace('日', "");
+        field.parse().ok()
+    });
+    let episodes = fields().find_map(|field| {
+        let count = field.strip_suffix(EPISODE_SUFFIXES)?;
+        count
+            .strip_prefix("共")
+            .unwrap_or(count)
+            .trim()
+            .parse()
+            .ok()
+    });
+    (info, release_date, episodes)
+}
+
+/// Returns the URL of the cover thumbnail. Covers are linked without
+/// the scheme, while subjects without one show a placeholder from the
+/// site itself, which is ignored.
+fn get_item_cover_url(elem: &ElementDataRef) -> Option<String> {
+    let img = elem.query_selector(".subCover img")?;
+    let attrs = img.attributes.borrow();
+    let src = attrs.get(local_name!("src"))?;
+    match src.strip_prefix("//") {
+        Some(src) => Some(format!("https://{}", src)),
+        None if src.starts_with("http") => Some(src.to_string()),
+        None => None,
+    }
+}
+
+fn get_item_site_rank(elem: &ElementDataRef) -> Option<u32> {
+    // The rank is shown as e.g. "Rank 123".
+    let text = elem.query_selector(".rank")?.text_contents();
+    text.trim_start_matches(|c: char| !c.is_ascii_digit())
+        .trim()
+        .parse()
+        .ok()
+}
+
+fn get_item_site_score(elem: &ElementDataRef) -> Option<f32> {
+    let text = elem.query_selector(".rateInfo .fade")?.text_contents();
+    text.trim().parse().ok()
+}
+
+fn generate_item_from_node(elem: &ElementDataRef) -> Result<Item, ParseError> {
+    let id = get_item_id(elem)?;
+    let title = get_item_title(elem, id)?;
+    let (title_cn, title_original) = get_item_titles(elem);
+    let (info, release_date, episodes) = get_item_info(elem);
+    Ok(Item {
+        id,
+        title,
+        title_cn,
+        title_original,
+        rating: get_item_rating(elem, id)?,
+        tags: get_item_tags(elem, id)?,
+        collected_at: get_item_collected_at(elem, id)?,
+        comment: get_item_comment(elem),
+        info,
+        release_date,
+        episodes,
+        cover_url: get_item_cover_url(elem),
+        site_rank: get_item_site_rank(elem),
+        site_score: get_item_site_score(elem),
+        // List pages don't tell which list they are, so leave it to the
+        // caller to fill in.
+        ..Default::default()
+    })
+}
+
+/// Returns whether the document contains an item list at all. Bangumi
+/// renders an empty list for users without any collections, so a
+/// missing list means the page is an error page instead.
+#[cfg(any(feature = "blocking", feature = "async"))]
+pub fn has_item_list(html: &NodeRef) -> bool {
+    html.select_first("#browserItemList").is_ok()
+}
+
+#[cfg(any(feature = "blocking", feature = "async"))]
+pub struct PageInfo {
+    /// Number of pages according to the pager. Lists fitting in a single
+    /// page have no pager.
+    pub page_count: Option<usize>,
+    /// Number of items in the list according to the tab in the header.
+    pub total_items: Option<usize>,
+}
+
+#[cfg(any(feature = "blocking", feature = "async"))]
+pub fn get_page_info(html: &NodeRef) -> PageInfo {
+    static PAGE_PARAM: &str = "page=";
+    // The pager links to the last page, unless there are too many pages,
+    // in which case it shows "( current / total )" instead.
+    let linked_pages = html.select("#multipage a").unwrap().filter_map(|link| {
+        let attrs = link.attributes.borrow();
+        let href = attrs.get(local_name!("href"))?;
+        let start = href.find(PAGE_PARAM)? + PAGE_PARAM.len();
+        let digits = href[start..].split(|c: char| !c.is_ascii_digit()).next()?;
+        digits.parse().ok()
+    });
+    let edge_pages = html
+        .select("#multipage .p_edge")
+        .unwrap()
+        .filter_map(|edge| {
+            let text = edge.text_contents();
+            let (_, total) = text.split_once('/')?;
+            total
+                .trim_matches(|c: char| !c.is_ascii_digit())
+                .parse()
+                .ok()
+        });
+    let total_items = html
+        .select_first(".navSubTabs a.focus")
+        .ok()
+        .and_then(|tab| {
+            let text = tab.text_contents();
+            let start = text.rfind('(')? + 1;
+            let end = start + text[start..].find(')')?;
+            text[start..end].trim().parse().ok()
+        });
+    PageInfo {
+        page_count: linked_pages.chain(edge_pages).max(),
+        total_items,
+    }
+}
+
+/// Returns whether the document is the page of a subject. Bangumi
+/// responds to unknown subjects with an error page rather than 404.
+#[cfg(any(feature = "blocking", feature = "async"))]
+pub fn has_subject(html: &NodeRef) -> bool {
+    html.select_first("h1.nameSingle").is_ok()
+}
+
+/// Parses the community tags and ratings on a subject page.
+pub fn get_subject(html: &NodeRef) -> Result<Subject, ParseError> {
+    static ID_SELECTOR: &str = "h1.nameSingle>a";
+    static TAG_SELECTOR: &str = ".subject_tag_section .inner>a";
+    static RATING_SELECTOR: &str = "#ChartWarpper .horizontalChart>li";
+    let link = html
+        .select_first(ID_SELECTOR)
+        .map_err(|_| make_error(Field::Id, None, ID_SELECTOR, html))?;
+    let id = link
+        .attributes
+        .borrow()
+        .get(local_name!("href"))
+        .and_then(|href| href.strip_prefix("/subject/")?.parse().ok())
+        .ok_or_else(|| make_error(Field::Id, None, ID_SELECTOR, link.as_node()))?;
+    let parse_count = |elem: Option<ElementDataRef>| {
+        // Counts are shown either bare or in parentheses.
+        let text = elem?.text_contents();
+        text.trim_matches(|c: char| c.is_whitespace() || c == '(' || c == ')')
+            .parse()
+            .ok()
+    };
+
+    let mut tags = vec![];
+    for tag in html.select(TAG_SELECTOR).unwrap() {
+        let error = || make_error(Field::Tags, Some(id), TAG_SELECTOR, tag.as_node());
+        let name = tag
+            .query_selector("span")
+            .ok_or_else(error)?
+            .text_contents();
+        let count = parse_count(tag.query_selector("small")).ok_or_else(error)?;
+        tags.push(TagCount {
+            name: name.trim().to_string(),
+            count,
+        });
     }
+
+    let mut ratings = [0; MAX_RATING as usize];
+    for bar in html.select(RATING_SELECTOR).unwrap() {
+        let error = || make_error(Field::Rating, Some(id), RATING_SELECTOR, bar.as_node());
+        let rating: Rating = bar
+            .query_selector(".label")
+            .and_then(|label| label.text_contents().trim().parse().ok())
+            .filter(|rating| (1..=MAX_RATING).contains(rating))
+            .ok_or_else(error)?;
+        ratings[rating as usize - 1] =
+            parse_count(bar.query_selector(".count")).ok_or_else(error)?;
+    }
+    let votes = html
+        .select_first(r#"#ChartWarpper [property="v:votes"]"#)
+        .ok()
+        .and_then(|votes| parse_count(Some(votes)))
+        .unwrap_or_else(|| ratings.iter().sum());
+    let score = html
+        .select_first(r#"[property="v:average"]"#)
+        .ok()
+        .and_then(|score| score.text_contents().trim().parse().ok())
+        .filter(|&score| score > 0.0);
+    Ok(Subject {
+        id,
+        tags,
+        ratings,
+        votes,
+        score,
+    })
+}
+
+/// Returns whether the document is the profile page of a user. Unknown
+/// users get an error page instead.
+#[cfg(any(feature = "blocking", feature = "async"))]
+pub fn has_user_profile(html: &NodeRef) -> bool {
+    html.select_first("#headerProfile").is_ok()
 }
 
-fn generate_item_from_node(elem: &ElementDataRef) -> Item {
-    Item {
-        id: get_item_id(elem),
-        title: get_item_title(elem),
-        rating: get_item_rating(elem),
-        tags: get_item_tags(elem),
+/// Parses the profile page of the user. The join date and the uid are
+/// listed along with the linked services, as `2019-4-6 加入` and under a
+/// `UID` label, while the counts are in the links to the lists, e.g.
+/// `123 看过`.
+pub fn get_user_profile(html: &NodeRef, username: &str) -> Result<UserProfile, ParseError> {
+    static NICKNAME_SELECTOR: &str = "#headerProfile .name a";
+    let nickname = html
+        .select_first(NICKNAME_SELECTOR)
+        .map_err(|_| make_error(Field::Nickname, None, NICKNAME_SELECTOR, html))?
+        .text_contents();
+
+    let mut uid = None;
+    let mut joined_at = None;
+    for service in html.select(".network_service li").unwrap() {
+        let label = service
+            .query_selector(".service")
+            .map(|s| s.text_contents());
+        let tip = match service.query_selector(".tip") {
+            Some(tip) => tip.text_contents(),
+            None => continue,
+        };
+        let tip = tip.trim();
+        if label.as_deref().map(str::trim) == Some("UID") {
+            uid = tip.parse().ok();
+        } else if let Some(date) = tip.strip_suffix("加入") {
+            joined_at = date.parse().ok();
+        }
     }
+
+    let list_prefix = format!("/list/{}/", username);
+    let mut counts = HashMap::new();
+    for link in html.select("a[href]").unwrap() {
+        let attrs = link.attributes.borrow();
+        let href = attrs.get(local_name!("href")).unwrap();
+        let list = href.split_once(&list_prefix).and_then(|(category, state)| {
+            let category: Category = category.rsplit('/').next()?.parse().ok()?;
+            let state: State = state.parse().ok()?;
+            Some((category, state))
+        });
+        let text = link.text_contents();
+        let count = text.trim().split(|c: char| !c.is_ascii_digit()).next();
+        if let (Some(list), Some(Ok(count))) = (list, count.map(str::parse)) {
+            counts.entry(list).or_insert(count);
+        }
+    }
+
+    Ok(UserProfile {
+        username: username.to_string(),
+        nickname: nickname.trim().to_string(),
+        uid,
+        joined_at,
+        counts,
+    })
 }
 
-pub fn get_all_items(html: NodeRef) -> Vec<Item> {
-    html.select("#browserItemList>li")
+/// Parses the friend list on the friends page of a user.
+pub fn get_friends(html: &NodeRef) -> Vec<Friend> {
+    html.select("#memberUserList li a.avatar")
         .unwrap()
-        .map(|elem| generate_item_from_node(&elem))
+        .filter_map(|link| {
+            let attrs = link.attributes.borrow();
+            let username = attrs.get(local_name!("href"))?.rsplit_once("/user/")?.1;
+            let nickname = link.text_contents();
+            Some(Friend {
+                username: username.trim_end_matches('/').to_string(),
+                nickname: nickname.trim().to_string(),
+            })
+        })
         .collect()
 }
+
+/// Parses all items in the list. Returns the items along with the errors
+/// of the skipped items in lenient mode, and fails on the first error in
+/// strict mode.
+pub fn get_all_items(
+    html: NodeRef,
+    mode: ParseMode,
+) -> Result<(Vec<Item>, Vec<ParseError>), ParseError> {
+    let mut items = vec![];
+    let mut errors = vec![];
+    for elem in html.select("#browserItemList>li").unwrap() {
+        match generate_item_from_node(&elem) {
+            Ok(item) => items.push(item),
+            Err(e) if mode == ParseMode::Lenient => errors.push(e),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok((items, errors))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{get_all_items, get_friends, get_subject, get_user_profile, ParseMode};
+    use crate::data::{Category, Date, State};
+    use crate::error::Field;
+    use html5ever::tendril::TendrilSink;
+
+    fn parse(items: &str) -> kuchiki::NodeRef {
+        let html = format!("<ul id=\"browserItemList\">{}</ul>", items);
+        kuchiki::parse_html().one(html)
+    }
+
+    #[test]
+    fn test_parse_item() {
+        let doc = parse(
+            r#"<li id="item_42">
+                <a href="/subject/42" class="subCover cover ll"><span class="image"><img src="//lain.bgm.tv/pic/cover/c/ab/cd/42_xyz.jpg" class="cover"></span></a>
+                <h3><a href="/subject/42">中文名</a> <small>Original</small></h3>
+                <span class="rank"><small>Rank </small>123</span>
+                <p class="info tip"> 26话 / 2019年4月6日 / 外崎春雄 / 吾峠呼世晴 </p>
+                <p class="rateInfo">
+                    <small class="fade">7.6</small> <span class="tip_j">(1234人评分)</span>
+                </p>
+                <p class="collectInfo">
+                    <span class="tip_j">2019-4-6</span>
+                    <span class="starstop-s"><span class="starlight stars8"></span></span>
+                    <span class="tip"> 标签: TV 2019 </span>
+                </p>
+                <div id="comment_box"><div class="item"><div class="text"> 好看 </div></div></div>
+            </li>"#,
+        );
+        let (items, _) = get_all_items(doc, ParseMode::Strict).unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].id, 42);
+        assert_eq!(items[0].title, "Original");
+        assert_eq!(items[0].title_cn.as_deref(), Some("中文名"));
+        assert_eq!(items[0].title_original.as_deref(), Some("Original"));
+        assert_eq!(items[0].rating, Some(8));
+        assert_eq!(items[0].tags, ["TV", "2019"]);
+        let date = Date {
+            year: 2019,
+            month: 4,
+            day: 6,
+        };
+        assert_eq!(items[0].collected_at, Some(date));
+        assert_eq!(items[0].comment.as_deref(), Some("好看"));
+        assert_eq!(
+            items[0].info.as_deref(),
+            Some("26话 / 2019年4月6日 / 外崎春雄 / 吾峠呼世晴")
+        );
+        assert_eq!(items[0].release_date.unwrap().to_string(), "2019-04-06");
+        assert_eq!(items[0].episodes, Some(26));
+        assert_eq!(
+            items[0].cover_url.as_deref(),
+            Some("https://lain.bgm.tv/pic/cover/c/ab/cd/42_xyz.jpg")
+        );
+        assert_eq!(items[0].subject_url(), "https://bgm.tv/subject/42");
+        assert_eq!(items[0].site_rank, Some(123));
+        assert_eq!(items[0].site_score, Some(7.6));
+        assert_eq!(date.to_string(), "2019-04-06");
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let doc = parse(
+            r#"<li id="item_7"><h3><a>Title</a></h3><span class="starlight stars11"></span></li>"#,
+        );
+        let err = get_all_items(doc, ParseMode::Strict).unwrap_err();
+        assert_eq!(err.field, Field::Rating);
+        assert_eq!(err.item, Some(7));
+        assert_eq!(err.selector, ".starlight");
+        assert_eq!(err.snippet, r#"<span class="starlight stars11"></span>"#);
+
+        let doc = parse(r#"<li id="entry_7"></li>"#);
+        let err = get_all_items(doc, ParseMode::Strict).unwrap_err();
+        assert_eq!(err.field, Field::Id);
+        assert_eq!(err.item, None);
+
+        let long_title = "x".repeat(300);
+        let doc = parse(&format!(
+            r#"<li id="item_8"><h3><b>{}</b></h3></li>"#,
+            long_title
+        ));
+        let err = get_all_items(doc, ParseMode::Strict).unwrap_err();
+        assert_eq!(err.field, Field::Title);
+        assert!(err.snippet.starts_with("<h3><b>xxx"));
+        assert!(err.snippet.ends_with("x…"));
+    }
+
+    #[test]
+    fn test_lenient_parsing() {
+        let doc = parse(
+            r#"<li id="item_1"><h3><a>One</a></h3></li>
+            <li id="item_2"><h3><a>Two</a></h3><p class="collectInfo"><span class="tip">tags</span></p></li>
+            <li id="item_3"><h3><a>Three</a></h3></li>"#,
+        );
+        let (items, errors) = get_all_items(doc, ParseMode::Lenient).unwrap();
+        assert_eq!(items.iter().map(|i| i.id).collect::<Vec<_>>(), [1, 3]);
+        assert_eq!(items[0].title_cn, None);
+        assert_eq!(items[0].title_original.as_deref(), Some("One"));
+        assert_eq!(items[0].info, None);
+        assert_eq!(items[0].site_rank, None);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, Field::Tags);
+        assert_eq!(errors[0].item, Some(2));
+    }
+
+    #[test]
+    #[cfg(any(feature = "blocking", feature = "async"))]
+    fn test_page_info() {
+        use super::get_page_info;
+
+        let doc = kuchiki::parse_html().one(
+            r#"<ul class="navSubTabs">
+                <li><a href="/anime/list/someone/wish"><span>想看 (12)</span></a></li>
+                <li><a href="/anime/list/someone/collect" class="focus"><span>看过 (280)</span></a></li>
+            </ul>
+            <div id="multipage"><div class="page_inner">
+                <a href="?page=1" class="p">1</a>
+                <strong class="p_cur">2</strong>
+                <a href="?page=3" class="p">3</a>
+                <a href="?page=3" class="p">&rsaquo;&rsaquo;</a>
+                <a href="?orderby=date&page=12" class="p">&rsaquo;|</a>
+            </div></div>"#,
+        );
+        let info = get_page_info(&doc);
+        assert_eq!(info.page_count, Some(12));
+        assert_eq!(info.total_items, Some(280));
+
+        let doc = kuchiki::parse_html().one(
+            r#"<div id="multipage"><div class="page_inner">
+                <a href="?page=1" class="p">1</a>
+                <a href="?page=2" class="p">2</a>
+                <span class="p_edge">( 1&nbsp;/&nbsp;35 )</span>
+            </div></div>"#,
+        );
+        assert_eq!(get_page_info(&doc).page_count, Some(35));
+
+        let info = get_page_info(&parse(""));
+        assert_eq!(info.page_count, None);
+        assert_eq!(info.total_items, None);
+    }
+
+    #[test]
+    fn test_parse_subject() {
+        let doc = kuchiki::parse_html().one(
+            r#"<h1 class="nameSingle"><a href="/subject/42">Original</a></h1>
+            <div class="subject_tag_section"><div class="inner">
+                <a href="/anime/tag/TV" class="l"><span>TV</span> <small class="grey">1234</small></a>
+                <a href="/anime/tag/2019" class="l"><span>2019</span> <small class="grey">56</small></a>
+            </div></div>
+            <div class="global_score"><span class="number" property="v:average">7.6</span></div>
+            <div id="ChartWarpper" class="chartWrapper">
+                <div class="chart_desc"><small class="grey"><span property="v:votes">110</span> votes</small></div>
+                <ul class="horizontalChart">
+                    <li><a title="100人"><span class="label">10</span><span class="count">(100)</span></a></li>
+                    <li><a title="10人"><span class="label">8</span><span class="count">(10)</span></a></li>
+                </ul>
+            </div>"#,
+        );
+        let subject = get_subject(&doc).unwrap();
+        assert_eq!(subject.id, 42);
+        let tags: Vec<_> = subject
+            .tags
+            .iter()
+            .map(|tag| (tag.name.as_str(), tag.count))
+            .collect();
+        assert_eq!(tags, [("TV", 1234), ("2019", 56)]);
+        assert_eq!(subject.ratings, [0, 0, 0, 0, 0, 0, 0, 10, 0, 100]);
+        assert_eq!(subject.votes, 110);
+        assert_eq!(subject.score, Some(7.6));
+
+        let doc = kuchiki::parse_html().one(
+            r#"<h1 class="nameSingle"><a href="/subject/42">Original</a></h1>
+            <div class="subject_tag_section"><div class="inner">
+                <a href="/anime/tag/TV" class="l"><span>TV</span> <small>many</small></a>
+            </div></div>"#,
+        );
+        let err = get_subject(&doc).unwrap_err();
+        assert_eq!(err.field, Field::Tags);
+        assert_eq!(err.item, Some(42));
+    }
+
+    #[test]
+    fn test_parse_user_profile() {
+        let doc = kuchiki::parse_html().one(
+            r#"<div id="headerProfile"><div class="name"><a href="/user/someone">昵称</a></div></div>
+            <ul class="network_service">
+                <li><span class="service">Bangumi</span><span class="tip">2019-4-6 加入</span></li>
+                <li><span class="service">UID</span><span class="tip">12345</span></li>
+            </ul>
+            <div id="anime" class="horizontalOptions"><ul>
+                <li class="title"><h2>动画</h2></li>
+                <li><a href="/anime/list/someone/wish">12 想看</a></li>
+                <li><a href="https://bgm.tv/anime/list/someone/collect">340 看过</a></li>
+                <li><a href="/anime/list/someone/on_hold">搁置</a></li>
+            </ul></div>
+            <a href="/book/list/other/collect">5 读过</a>"#,
+        );
+        let profile = get_user_profile(&doc, "someone").unwrap();
+        assert_eq!(profile.nickname, "昵称");
+        assert_eq!(profile.uid, Some(12345));
+        assert_eq!(profile.joined_at.unwrap().to_string(), "2019-04-06");
+        assert_eq!(profile.count(Category::Anime, State::Wish), 12);
+        assert_eq!(profile.count(Category::Anime, State::Collect), 340);
+        assert_eq!(profile.counts.len(), 2);
+
+        let doc = kuchiki::parse_html().one("<p>not found</p>");
+        let err = get_user_profile(&doc, "someone").unwrap_err();
+        assert_eq!(err.field, Field::Nickname);
+    }
+
+    #[test]
+    fn test_parse_friends() {
+        let doc = kuchiki::parse_html().one(
+            r#"<ul id="memberUserList" class="usersSmall">
+                <li class="user"><div class="userContainer"><strong>
+                    <a href="/user/alice" class="avatar"><span class="avatarNeue"></span> 爱丽丝 </a>
+                </strong></div></li>
+                <li class="user"><div class="userContainer"><strong>
+                    <a href="https://bgm.tv/user/12345" class="avatar">Bob</a>
+                </strong></div></li>
+            </ul>"#,
+        );
+        let friends = get_friends(&doc);
+        let friends: Vec<_> = friends
+            .iter()
+            .map(|f| (f.username.as_str(), f.nickname.as_str()))
+            .collect();
+        assert_eq!(friends, [("alice", "爱丽丝"), ("12345", "Bob")]);
+    }
+}
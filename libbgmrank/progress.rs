@@ -0,0 +1,48 @@
+use crate::data::{Category, State};
+use crate::error::ParseError;
+use std::time::Duration;
+
+/// Events reported to the observer while fetching lists.
+#[derive(Clone, Debug)]
+pub enum Progress {
+    /// A page is about to be fetched.
+    PageStarted {
+        category: Category,
+        state: State,
+        page: usize,
+    },
+    /// A page has been fetched and parsed.
+    PageParsed {
+        category: Category,
+        state: State,
+        page: usize,
+        items: usize,
+    },
+    /// The number of pages of a list has become known, along with the
+    /// number of items in it if the site shows that.
+    PageCountKnown {
+        category: Category,
+        state: State,
+        pages: usize,
+        total_items: Option<usize>,
+    },
+    /// Fetching a page failed with a transient error, and will be retried
+    /// after the given delay.
+    Retrying {
+        category: Category,
+        state: State,
+        page: usize,
+        attempt: u32,
+        delay: Duration,
+    },
+    /// An item on a page failed to parse, and has been skipped because
+    /// the fetcher is in lenient mode.
+    ItemSkipped {
+        category: Category,
+        state: State,
+        page: usize,
+        error: ParseError,
+    },
+    /// All pages have been fetched.
+    Finished { items: usize },
+}
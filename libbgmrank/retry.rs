@@ -0,0 +1,100 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+/// Controls how failed requests are retried.
+///
+/// Requests are retried on connection errors, timeouts, `429 Too Many
+/// Requests` and `5xx` responses. The delay before the n-th retry is
+/// `initial_backoff * 2^(n-1)`, capped at `max_backoff`, or the delay
+/// given by the `Retry-After` header if the server asks for longer.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts for each request, including the first.
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Randomizes each delay between half and the full amount so that
+    /// concurrent clients don't retry in lockstep.
+    pub jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 4,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+            jitter: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy which never retries.
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the delay before the given retry, counting from 1.
+    pub(crate) fn backoff(&self, retry: u32, retry_after: Option<Duration>) -> Duration {
+        let exp = retry.saturating_sub(1).min(31);
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(1 << exp)
+            .min(self.max_backoff);
+        let backoff = if self.jitter {
+            let half = backoff / 2;
+            half + half.mul_f64(random_fraction())
+        } else {
+            backoff
+        };
+        match retry_after {
+            Some(retry_after) => backoff.max(retry_after),
+            None => backoff,
+        }
+    }
+}
+
+/// Returns a pseudo-random number in `[0, 1)`. This doesn't need to be
+/// good randomness, so we just use the random keys of `RandomState`.
+fn random_fraction() -> f64 {
+    let hash = RandomState::new().build_hasher().finish();
+    (hash >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod test {
+    use super::RetryPolicy;
+    use std::time::Duration;
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(500),
+            jitter: false,
+        };
+        let ms = |retry, retry_after| policy.backoff(retry, retry_after).as_millis();
+        assert_eq!(ms(1, None), 100);
+        assert_eq!(ms(2, None), 200);
+        assert_eq!(ms(3, None), 400);
+        assert_eq!(ms(4, None), 500);
+        assert_eq!(ms(100, None), 500);
+        assert_eq!(ms(1, Some(Duration::from_secs(2))), 2000);
+        assert_eq!(ms(3, Some(Duration::from_millis(10))), 400);
+
+        let policy = RetryPolicy {
+            jitter: true,
+            ..policy
+        };
+        for _ in 0..20 {
+            let backoff = policy.backoff(2, None).as_millis();
+            assert!((100..=200).contains(&backoff));
+        }
+    }
+}
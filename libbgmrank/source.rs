@@ -0,0 +1,112 @@
+use crate::data::{Category, Item, State};
+use crate::error::Error;
+use std::collections::HashMap;
+
+/// Something that provides the items in the lists of users, e.g. the
+/// site, saved pages, or an export.
+pub trait CollectionSource {
+    fn get_items(
+        &self,
+        username: &str,
+        category: Category,
+        state: State,
+    ) -> Result<Vec<Item>, Error>;
+
+    /// Gets the items of all the given lists, in the order of the lists.
+    fn get_all_items(
+        &self,
+        username: &str,
+        lists: &[(Category, State)],
+    ) -> Result<Vec<Item>, Error> {
+        let mut result = vec![];
+        for &(category, state) in lists {
+            result.extend(self.get_items(username, category, state)?);
+        }
+        Ok(result)
+    }
+}
+
+/// Records the list the items were got from on them.
+pub(crate) fn set_list(items: &mut [Item], category: Category, state: State) {
+    for item in items {
+        item.category = Some(category);
+        item.state = Some(state);
+    }
+}
+
+/// A source serving items held in memory, mainly useful in tests.
+///
+/// Lists which have not been inserted are empty, while unknown users
+/// are reported as not found.
+#[derive(Default)]
+pub struct MemorySource {
+    lists: HashMap<(String, Category, State), Vec<Item>>,
+}
+
+impl MemorySource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds items to the given list.
+    pub fn insert(
+        &mut self,
+        username: &str,
+        category: Category,
+        state: State,
+        items: impl IntoIterator<Item = Item>,
+    ) {
+        self.lists
+            .entry((username.to_string(), category, state))
+            .or_default()
+            .extend(items);
+    }
+}
+
+impl CollectionSource for MemorySource {
+    fn get_items(
+        &self,
+        username: &str,
+        category: Category,
+        state: State,
+    ) -> Result<Vec<Item>, Error> {
+        if !self.lists.keys().any(|(user, _, _)| user == username) {
+            return Err(Error::UserNotFound(username.to_string()));
+        }
+        let key = (username.to_string(), category, state);
+        let mut items = self.lists.get(&key).cloned().unwrap_or_default();
+        set_list(&mut items, category, state);
+        Ok(items)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CollectionSource, MemorySource};
+    use crate::data::{Category, Item, State};
+    use crate::error::Error;
+
+    #[test]
+    fn test_memory_source() {
+        let item = |id| Item {
+            id,
+            ..Default::default()
+        };
+        let mut source = MemorySource::new();
+        source.insert("someone", Category::Anime, State::Collect, [item(1)]);
+        source.insert("someone", Category::Book, State::Do, [item(2), item(3)]);
+        let lists = [
+            (Category::Book, State::Do),
+            (Category::Game, State::Wish),
+            (Category::Anime, State::Collect),
+        ];
+        let items = source.get_all_items("someone", &lists).unwrap();
+        assert_eq!(items.iter().map(|i| i.id).collect::<Vec<_>>(), [2, 3, 1]);
+        assert_eq!(items[0].category, Some(Category::Book));
+        assert_eq!(items[0].state, Some(State::Do));
+        assert_eq!(items[2].category, Some(Category::Anime));
+
+        let result = source.get_items("nobody", Category::Anime, State::Collect);
+        assert!(matches!(result, Err(Error::UserNotFound(_))));
+    }
+}
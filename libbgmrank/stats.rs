@@ -1,6 +1,7 @@
 use crate::classifier;
-use crate::data::{Item, Rating, MAX_RATING};
+use crate::data::{Item, Rating, Subject, MAX_RATING};
 use std::cmp::{Ordering, PartialOrd};
+use std::collections::BTreeMap;
 use std::fmt;
 use std::fmt::{Display, Formatter};
 use std::iter::FromIterator;
@@ -37,11 +38,17 @@ pub struct Stats {
     pub rating: Interval,
 }
 
+#[derive(Default)]
 pub struct Histogram {
     ratings: [usize; MAX_RATING as usize + 1],
 }
 
 impl Histogram {
+    /// Creates an empty histogram, to be filled by indexing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
     pub fn get_max_rated(&self) -> (Rating, usize) {
         self.ratings.iter().enumerate().skip(1).fold(
             (0, 0),
@@ -61,7 +68,7 @@ impl Histogram {
             .enumerate()
             .skip(1)
             .fold((0, 0), |(count, sum), (rating, &num)| {
-                (count + num, sum + (rating as usize * num))
+                (count + num, sum + (rating * num))
             });
         let avg = sum as f32 / rated as f32;
         let var = self
@@ -89,9 +96,7 @@ impl<'a> FromIterator<&'a Item> for Histogram {
     where
         Iter: IntoIterator<Item = &'a Item>,
     {
-        let mut result = Histogram {
-            ratings: [0; MAX_RATING as usize + 1],
-        };
+        let mut result = Histogram::new();
         for item in iter {
             result[item.rating] += 1;
         }
@@ -118,6 +123,18 @@ impl IndexMut<Option<Rating>> for Histogram {
     }
 }
 
+impl Subject {
+    /// Returns the histogram of the votes on the site, to compare with
+    /// the ratings of users.
+    pub fn histogram(&self) -> Histogram {
+        let mut result = Histogram::new();
+        for (rating, &votes) in (1..=MAX_RATING).zip(&self.ratings) {
+            result[Some(rating)] = votes;
+        }
+        result
+    }
+}
+
 pub struct TagStats {
     pub tag: String,
     pub stats: Stats,
@@ -147,10 +164,90 @@ pub fn generate_tag_stats(all_items: &[Item]) -> Vec<TagStats> {
     result
 }
 
+/// How the ratings of a user compare to the site scores of the same
+/// items.
+pub struct Deviation {
+    /// Number of items with both a rating and a site score.
+    pub count: usize,
+    /// Average of the ratings minus the site scores, which is positive
+    /// when the user rates higher than the site.
+    pub avg: f32,
+}
+
+/// Returns the rating of the item minus its site score.
+pub fn get_score_diff(item: &Item) -> Option<f32> {
+    Some(f32::from(item.rating?) - item.site_score?)
+}
+
+/// Returns the deviation of the items from their site scores, or `None`
+/// if no item has both a rating and a site score.
+pub fn get_deviation<'a>(items: impl IntoIterator<Item = &'a Item>) -> Option<Deviation> {
+    let (count, sum) = items
+        .into_iter()
+        .filter_map(get_score_diff)
+        .fold((0, 0f32), |(count, sum), diff| (count + 1, sum + diff));
+    if count == 0 {
+        return None;
+    }
+    Some(Deviation {
+        count,
+        avg: sum / count as f32,
+    })
+}
+
+pub struct TagDeviation {
+    pub tag: String,
+    pub deviation: Deviation,
+}
+
+/// Returns the deviation from the site scores for each tag, from the
+/// most overrated tag to the most underrated one.
+pub fn generate_tag_deviations(all_items: &[Item]) -> Vec<TagDeviation> {
+    let mut result: Vec<TagDeviation> = classifier::classify_by_tags(all_items)
+        .into_iter()
+        .filter_map(|(tag, items)| {
+            let deviation = get_deviation(items)?;
+            Some(TagDeviation { tag, deviation })
+        })
+        .collect();
+    result.sort_by(|l, r| l.deviation.avg.total_cmp(&r.deviation.avg).reverse());
+    result
+}
+
+pub struct GroupStats<K> {
+    pub key: K,
+    pub stats: Stats,
+}
+
+/// Groups the items by the given key, e.g. the release year, skipping
+/// items without a key, and returns the stats of each group in the order
+/// of the keys.
+pub fn generate_stats_by<K: Ord>(
+    all_items: &[Item],
+    key: impl Fn(&Item) -> Option<K>,
+) -> Vec<GroupStats<K>> {
+    let mut groups = BTreeMap::new();
+    for item in all_items {
+        if let Some(key) = key(item) {
+            groups.entry(key).or_insert_with(Vec::new).push(item);
+        }
+    }
+    groups
+        .into_iter()
+        .map(|(key, items)| {
+            let hist: Histogram = items.into_iter().collect();
+            GroupStats {
+                key,
+                stats: hist.get_stats(),
+            }
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod test {
-    use super::Histogram;
-    use crate::data::Item;
+    use super::{generate_stats_by, generate_tag_deviations, get_deviation, Histogram};
+    use crate::data::{Item, Subject};
     use float_cmp::ApproxEqUlps;
 
     macro_rules! item_with_rating {
@@ -229,8 +326,8 @@ mod test {
         let stats = hist.get_stats();
         assert_eq!(stats.total, 665);
         assert_eq!(stats.rated, 605);
-        assert!(stats.rating.avg.approx_eq_ulps(&5.77024793388, 1));
-        assert!(stats.rating.stdev.approx_eq_ulps(&3.22415072111, 1));
+        assert!(stats.rating.avg.approx_eq_ulps(&5.770_248, 1));
+        assert!(stats.rating.stdev.approx_eq_ulps(&3.224_150_7, 1));
 
         let hist = Histogram {
             ratings: [10, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
@@ -239,4 +336,70 @@ mod test {
         assert_eq!(stats.rated, 0);
         assert!(stats.rating.is_nan());
     }
+
+    #[test]
+    fn test_generate_stats_by() {
+        let item = |episodes, rating| Item {
+            episodes,
+            rating,
+            ..Default::default()
+        };
+        let items = [
+            item(Some(24), Some(8)),
+            item(Some(12), Some(6)),
+            item(None, Some(10)),
+            item(Some(12), Some(8)),
+            item(Some(24), None),
+        ];
+        let groups = generate_stats_by(&items, |item| item.episodes);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].key, 12);
+        assert_eq!(groups[0].stats.total, 2);
+        assert!(groups[0].stats.rating.avg.approx_eq_ulps(&7.0, 2));
+        assert_eq!(groups[1].key, 24);
+        assert_eq!(groups[1].stats.total, 2);
+        assert_eq!(groups[1].stats.rated, 1);
+    }
+
+    #[test]
+    fn test_deviation() {
+        let item = |tag: &str, rating, site_score| Item {
+            tags: vec![tag.to_string()],
+            rating,
+            site_score,
+            ..Default::default()
+        };
+        let items = [
+            item("a", Some(8), Some(7.0)),
+            item("a", Some(9), Some(7.0)),
+            item("b", Some(5), Some(7.0)),
+            item("b", None, Some(7.0)),
+            item("c", Some(5), None),
+        ];
+        let deviation = get_deviation(&items).unwrap();
+        assert_eq!(deviation.count, 3);
+        assert!(deviation.avg.approx_eq_ulps(&(1.0 / 3.0), 2));
+        let tags = generate_tag_deviations(&items);
+        assert_eq!(tags.len(), 2);
+        assert_eq!(tags[0].tag, "a");
+        assert!(tags[0].deviation.avg.approx_eq_ulps(&1.5, 2));
+        assert_eq!(tags[1].tag, "b");
+        assert_eq!(tags[1].deviation.count, 1);
+        assert!(get_deviation(&items[3..]).is_none());
+    }
+
+    #[test]
+    fn test_subject_histogram() {
+        let subject = Subject {
+            ratings: [0, 0, 0, 0, 0, 0, 1, 2, 1, 0],
+            votes: 4,
+            ..Default::default()
+        };
+        let hist = subject.histogram();
+        assert_eq!(hist[Some(8)], 2);
+        assert_eq!(hist[None], 0);
+        let stats = hist.get_stats();
+        assert_eq!(stats.total, 4);
+        assert!(stats.rating.avg.approx_eq_ulps(&8.0, 2));
+    }
 }
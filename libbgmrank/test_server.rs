@@ -0,0 +1,164 @@
+//! A minimal HTTP server on localhost for testing the fetcher.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(&'static str, String)>,
+    pub body: String,
+}
+
+impl Response {
+    pub fn ok(body: impl Into<String>) -> Self {
+        Response {
+            status: 200,
+            headers: vec![],
+            body: body.into(),
+        }
+    }
+
+    pub fn status(status: u16) -> Self {
+        Response {
+            status,
+            headers: vec![],
+            body: String::new(),
+        }
+    }
+}
+
+pub struct TestServer {
+    pub base_url: String,
+    requests: Arc<AtomicUsize>,
+    heads: Arc<Mutex<Vec<String>>>,
+}
+
+impl TestServer {
+    /// Starts a server which answers every request with the response
+    /// returned by `handler` for the request target and the zero-based
+    /// index of the request.
+    pub fn start<F>(handler: F) -> Self
+    where
+        F: Fn(&str, usize) -> Response + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let heads = Arc::new(Mutex::new(vec![]));
+        let recorded_heads = heads.clone();
+        let handler = Arc::new(handler);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => break,
+                };
+                let counter = counter.clone();
+                let heads = recorded_heads.clone();
+                let handler = handler.clone();
+                thread::spawn(move || handle_connection(stream, &counter, &heads, &*handler));
+            }
+        });
+        TestServer {
+            base_url,
+            requests,
+            heads,
+        }
+    }
+
+    /// Returns the number of requests received so far.
+    pub fn requests(&self) -> usize {
+        self.requests.load(Ordering::SeqCst)
+    }
+
+    /// Returns the request lines and headers of the requests received so
+    /// far, with header names in lowercase.
+    pub fn request_heads(&self) -> Vec<String> {
+        self.heads.lock().unwrap().clone()
+    }
+}
+
+fn handle_connection(
+    stream: TcpStream,
+    counter: &AtomicUsize,
+    heads: &Mutex<Vec<String>>,
+    handler: &dyn Fn(&str, usize) -> Response,
+) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).is_err() {
+        return;
+    }
+    let mut request_head = request_line.clone();
+    loop {
+        let mut line = String::new();
+        match reader.read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) if line == "\r\n" => break,
+            Ok(_) => {}
+        }
+        match line.split_once(':') {
+            Some((name, value)) => request_head += &format!("{}:{}", name.to_lowercase(), value),
+            None => request_head += &line,
+        }
+    }
+    heads.lock().unwrap().push(request_head);
+    let target = request_line.split(' ').nth(1).unwrap_or("/");
+    let index = counter.fetch_add(1, Ordering::SeqCst);
+    let response = handler(target, index);
+    let mut stream = stream;
+    let mut head = format!(
+        "HTTP/1.1 {} Status\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        response.body.len()
+    );
+    for (name, value) in response.headers {
+        head += &format!("{}: {}\r\n", name, value);
+    }
+    head += "\r\n";
+    let _ = stream.write_all(head.as_bytes());
+    let _ = stream.write_all(response.body.as_bytes());
+}
+
+/// Generates a list page containing items with the given ids.
+pub fn list_page(ids: impl IntoIterator<Item = u32>) -> String {
+    let items: String = ids
+        .into_iter()
+        .map(|id| {
+            format!(
+                "<li id=\"item_{0}\"><h3><a href=\"/subject/{0}\">Item {0}</a></h3></li>",
+                id
+            )
+        })
+        .collect();
+    format!("<ul id=\"browserItemList\">{}</ul>", items)
+}
+
+/// Generates a page of collections in the format of the v0 API, with
+/// the given ids out of `total` collections.
+#[cfg(feature = "blocking")]
+pub fn collection_page(ids: impl IntoIterator<Item = u32>, total: usize) -> String {
+    let data: Vec<_> = ids
+        .into_iter()
+        .map(|id| {
+            format!(
+                concat!(
+                    r#"{{"updated_at":"2023-04-01T20:13:42+08:00","comment":null,"tags":["TV"],"#,
+                    r#""subject":{{"id":{0},"type":2,"name":"Item {0}","name_cn":""}},"#,
+                    r#""subject_id":{0},"subject_type":2,"type":2,"rate":{1},"private":false}}"#
+                ),
+                id,
+                id % 11
+            )
+        })
+        .collect();
+    format!(
+        r#"{{"data":[{}],"total":{},"limit":50,"offset":0}}"#,
+        data.join(","),
+        total
+    )
+}